@@ -0,0 +1,68 @@
+use nn::{Sequential, Loss, TrainData};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum AttackMethod {
+  /// Fast gradient sign method: a single step of size `epsilon`.
+  Fgsm,
+  /// Projected gradient descent: `steps` signed steps of size `step_size`, each followed by a
+  /// projection back into the `epsilon` box around the example.
+  Pgd,
+}
+
+/// Perturbation of inputs on the [0, 1] pixel scale `load_idx_images` produces. Every pixel moves
+/// by at most `epsilon` and stays within [0, 1].
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct AttackConfig {
+  pub method: AttackMethod,
+  pub epsilon: f32,
+  #[serde(default = "AttackConfig::default_steps")]
+  pub steps: usize,
+  /// Defaults to `epsilon / 4`.
+  #[serde(default)]
+  pub step_size: Option<f32>,
+}
+
+impl AttackConfig {
+  fn default_steps() -> usize {
+    10
+  }
+}
+
+/// Replaces a fraction of every training batch by adversarial examples against the current
+/// network.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct AdversarialTraining {
+  pub attack: AttackConfig,
+  pub ratio: f32,
+}
+
+impl Sequential {
+  /// Perturbs `example` within the attack's budget to increase the loss of the output against
+  /// `target`.
+  pub fn adversarial_example(&self, example: &[f32], target: &[f32], loss: Loss, conf: &AttackConfig) -> Vec<f32> {
+    let (steps, step_size) = match conf.method {
+      AttackMethod::Fgsm => (1, conf.epsilon),
+      AttackMethod::Pgd => (conf.steps, conf.step_size.unwrap_or(conf.epsilon / 4.0)),
+    };
+
+    let mut adversarial = example.to_vec();
+    for _ in 0..steps {
+      let gradient = self.input_gradient(adversarial.clone(), target.to_vec(), loss).1;
+      for ((x, &original), g) in adversarial.iter_mut().zip(example).zip(gradient) {
+        let stepped = *x + step_size * g.signum();
+        *x = stepped.max(original - conf.epsilon).min(original + conf.epsilon).max(0.0).min(1.0);
+      }
+    }
+    adversarial
+  }
+
+  /// Replaces the leading `ratio` of `batch` by adversarial examples with the same targets.
+  pub fn mix_adversarial_examples(&self, batch: &mut TrainData, conf: &AdversarialTraining, loss: Loss) {
+    use rayon::prelude::*;
+
+    let attacked = ((batch.len() as f32 * conf.ratio).round() as usize).min(batch.len());
+    batch[..attacked].par_iter_mut().for_each(|&mut (ref mut input, ref target)| {
+      *input = self.adversarial_example(input, target, loss, &conf.attack);
+    });
+  }
+}
@@ -0,0 +1,120 @@
+use na::DVector;
+
+/// Normalises every unit over the batch, then applies a learned scale and shift. Outside of
+/// training the running statistics gathered by `update_statistics` are used instead.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct BatchNorm {
+  pub scale: DVector<f32>,
+  pub shift: DVector<f32>,
+  pub running_mean: DVector<f32>,
+  pub running_var: DVector<f32>,
+  pub momentum: f32,
+  pub epsilon: f32,
+}
+
+pub struct BatchNormCache {
+  normalized: Vec<DVector<f32>>,
+  mean: Vec<f32>,
+  var: Vec<f32>,
+  inv_std: Vec<f32>,
+  train: bool,
+}
+
+impl BatchNorm {
+  pub fn new(size: usize) -> BatchNorm {
+    BatchNorm {
+      scale: DVector::from_fn(size, |_| 1.0),
+      shift: DVector::new_zeros(size),
+      running_mean: DVector::new_zeros(size),
+      running_var: DVector::from_fn(size, |_| 1.0),
+      momentum: 0.1,
+      epsilon: 1e-5,
+    }
+  }
+
+  pub fn reset_params(&mut self) {
+    let size = self.scale.len();
+    *self = BatchNorm { momentum: self.momentum, epsilon: self.epsilon, ..BatchNorm::new(size) };
+  }
+
+  pub fn forward(&self, inputs: &[DVector<f32>], train: bool) -> (Vec<DVector<f32>>, BatchNormCache) {
+    let size = self.scale.len();
+    let n = inputs.len() as f32;
+
+    let (mean, var) = if train {
+      let mean = (0..size).map(|u| inputs.iter().map(|x| x[u]).sum::<f32>() / n).collect::<Vec<_>>();
+      let var = (0..size).map(|u| inputs.iter().map(|x| (x[u] - mean[u]) * (x[u] - mean[u])).sum::<f32>() / n).collect::<Vec<_>>();
+      (mean, var)
+    } else {
+      (self.running_mean.at.clone(), self.running_var.at.clone())
+    };
+    let inv_std = var.iter().map(|v| 1.0 / (v + self.epsilon).sqrt()).collect::<Vec<_>>();
+
+    let normalized = inputs.iter()
+      .map(|x| (0..size).map(|u| (x[u] - mean[u]) * inv_std[u]).collect::<DVector<f32>>())
+      .collect::<Vec<_>>();
+    let outputs = normalized.iter()
+      .map(|xh| (0..size).map(|u| xh[u] * self.scale[u] + self.shift[u]).collect())
+      .collect();
+
+    (outputs, BatchNormCache { normalized: normalized, mean: mean, var: var, inv_std: inv_std, train: train })
+  }
+
+  /// Returns the gradient w.r.t. the inputs and the gradients of `[scale, shift]`. In training
+  /// mode the gradient accounts for every example's effect on the batch statistics.
+  pub fn backward(&self, cache: &BatchNormCache, output_deltas: &[DVector<f32>]) -> (Vec<DVector<f32>>, Vec<Vec<f32>>) {
+    let size = self.scale.len();
+    let n = output_deltas.len() as f32;
+
+    let scale_update = (0..size).map(|u| output_deltas.iter().zip(&cache.normalized).map(|(d, xh)| d[u] * xh[u]).sum::<f32>()).collect::<Vec<_>>();
+    let shift_update = (0..size).map(|u| output_deltas.iter().map(|d| d[u]).sum::<f32>()).collect::<Vec<_>>();
+
+    let input_deltas = output_deltas.iter().zip(&cache.normalized)
+      .map(|(d, xh)| (0..size).map(|u| {
+        let d_norm = d[u] * self.scale[u];
+        if cache.train {
+          cache.inv_std[u] / n * (n * d_norm - self.scale[u] * (shift_update[u] + xh[u] * scale_update[u]))
+        } else {
+          d_norm * cache.inv_std[u]
+        }
+      }).collect())
+      .collect();
+
+    (input_deltas, vec![scale_update, shift_update])
+  }
+
+  /// Folds the statistics of a training batch into the running mean and variance.
+  pub fn update_statistics(&mut self, cache: &BatchNormCache) {
+    if !cache.train {
+      return;
+    }
+    let n = cache.normalized.len() as f32;
+    let unbiased = if n > 1.0 { n / (n - 1.0) } else { 1.0 };
+
+    for u in 0..self.scale.len() {
+      self.running_mean[u] += self.momentum * (cache.mean[u] - self.running_mean[u]);
+      self.running_var[u] += self.momentum * (cache.var[u] * unbiased - self.running_var[u]);
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use rand::{SeedableRng, XorShiftRng};
+
+  use gradient_check::{check_weighted, layers};
+  use layer::{Layer, LayerKind};
+
+  #[test]
+  fn batch_norm_gradients() {
+    let mut layer = layers(r#"{"layers": [5, 4], "activation_coeffs": [1.5], "activation_fn": "sigmoid", "batch_norm": [true]}"#).remove(0);
+    layer.assign_random_weights(&mut XorShiftRng::from_seed([9, 2, 3, 4]));
+    // random weights reset the scale to one and the shift to zero, which would hide their errors
+    if let LayerKind::Dense(ref mut dense) = layer {
+      let bn = dense.batch_norm.as_mut().unwrap();
+      bn.scale.at = vec![0.5, 1.5, -1.0, 2.0];
+      bn.shift.at = vec![0.1, -0.2, 0.3, 0.0];
+    }
+    check_weighted(layer, 4);
+  }
+}
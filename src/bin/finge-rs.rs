@@ -7,7 +7,6 @@ extern crate nalgebra as na;
 extern crate rand;
 extern crate serde;
 extern crate serde_json as sj;
-#[macro_use] extern crate serde_derive;
 extern crate image as img;
 extern crate byteorder as bo;
 extern crate rayon;
@@ -26,33 +25,64 @@ fn main() {
   let args: clap::ArgMatches = program_args::get();
   match args.subcommand_name() {
     Some("train") => train(args.subcommand_matches("train").unwrap()),
-    // Some("test") => test(args.subcommand_matches("test").unwrap()),
+    Some("test") => test(args.subcommand_matches("test").unwrap()),
+    Some("fine-tune") => fine_tune(args.subcommand_matches("fine-tune").unwrap()),
+    Some("probe") => probe(args.subcommand_matches("probe").unwrap()),
+    Some("cluster") => cluster(args.subcommand_matches("cluster").unwrap()),
+    Some("evaluate") => evaluate(args.subcommand_matches("evaluate").unwrap()),
+    Some("score") => score(args.subcommand_matches("score").unwrap()),
+    Some("compare-pca") => compare_pca(args.subcommand_matches("compare-pca").unwrap()),
+    Some("embed") => embed(args.subcommand_matches("embed").unwrap()),
+    Some("interpolate") => interpolate_codes(args.subcommand_matches("interpolate").unwrap()),
+    Some("traverse") => traverse_codes(args.subcommand_matches("traverse").unwrap()),
     Some("dump-features") => dump_features(args.subcommand_matches("dump-features").unwrap()),
+    Some("visualize") => visualize(args.subcommand_matches("visualize").unwrap()),
+    Some("saliency") => saliency(args.subcommand_matches("saliency").unwrap()),
+    Some("attack") => attack(args.subcommand_matches("attack").unwrap()),
+    Some("inpaint") => inpaint(args.subcommand_matches("inpaint").unwrap()),
+    Some("denoise") => denoise(args.subcommand_matches("denoise").unwrap()),
     Some("sample") => sample(args.subcommand_matches("sample").unwrap()),
+    Some("inspect") => inspect(args.subcommand_matches("inspect").unwrap()),
+    Some("model") => {
+      let args = args.subcommand_matches("model").unwrap();
+      match args.subcommand_name() {
+        Some("edit") => model_edit(args.subcommand_matches("edit").unwrap()),
+        _ => {},
+      }
+    },
+    Some("som") => som(args.subcommand_matches("som").unwrap()),
     _ => {},
   }
 }
 
-fn train<'a>(args: &ArgMatches<'a>) {
-  use nn::*;
-  use rand::SeedableRng;
-
+/// Returns a flag that stays set until the user presses Ctrl-C, for training loops to poll.
+fn install_interrupt_flag() -> Arc<AtomicBool> {
   let learning = Arc::new(AtomicBool::new(true));
   let l = learning.clone();
   ctrlc::set_handler(move || {
     println!("Stopping...");
     l.store(false, Ordering::SeqCst);
   }).unwrap();
+  learning
+}
 
-  let conf = {
-    use std::fs::File;
-    match File::open(args.value_of("config").unwrap()) {
-      Ok(file) => sj::from_reader(file).unwrap(),
-      Err(_) => panic!("no config file"),
-    }
-  };
+fn load_json_config<T: serde::de::DeserializeOwned>(path: &str) -> T {
+  use std::fs::File;
+  match File::open(path) {
+    Ok(file) => sj::from_reader(file).unwrap(),
+    Err(_) => panic!("no config file"),
+  }
+}
+
+fn train<'a>(args: &ArgMatches<'a>) {
+  use nn::*;
+  use rand::SeedableRng;
 
-  let all_data = mnist::load_idx_images_halved("mnist/train-images.idx3-ubyte").unwrap();
+  let learning = install_interrupt_flag();
+
+  let mut conf: TrainConfig = load_json_config(args.value_of("config").unwrap());
+
+  let task = args.value_of("task").unwrap();
 
   // normalize input data
   // for ex in &mut train_data {
@@ -64,11 +94,7 @@ fn train<'a>(args: &ArgMatches<'a>) {
 
   let mut rng: rand::XorShiftRng = rand::XorShiftRng::from_seed(rand::random());
   let mut net = if let Some(model_path) = args.value_of("model") {
-    use std::fs::File;
-    use std::io::BufReader;
-
-    let mut file = BufReader::new(File::open(model_path).unwrap());
-    bc::deserialize_from(&mut file, bc::Infinite).unwrap()
+    Network::load(model_path).unwrap()
   } else {
     let defn = {
       use std::fs::File;
@@ -77,84 +103,586 @@ fn train<'a>(args: &ArgMatches<'a>) {
         Err(_) => panic!("no network definition found"),
       }
     };
-    let mut net = Network::from_definition(&defn);
+    let mut net = Network::from_definition(&defn).unwrap_or_else(|e| panic!("invalid network definition: {}", e));
     net.assign_random_weights(&mut rng);
     net
   };
-  let (train_data, validation_data) = Network::split_data_sequences_autoencoder(&mut rng, all_data, &conf);
-  // let ref_mut_rng = &mut rng;
-  net.train_autoencoder(|| {
-    let idx = ::rand::seq::sample_indices(&mut rng, train_data.len(), (conf.batch_size.unwrap_or(0.01) as f32 * train_data.len() as f32) as usize);
+  if task == "classify" {
+    let all_data = load_images("mnist/train-images.idx3-ubyte", net.input_size());
+    let labels = mnist::load_idx_labels("mnist/train-labels.idx1-ubyte").unwrap();
+    train_classifier(&mut net, all_data, &labels, &mut conf, &mut rng, learning);
+  } else if task == "classify-rows" {
+    let sequences = mnist::load_idx_image_rows("mnist/train-images.idx3-ubyte").unwrap();
+    let labels = mnist::load_idx_labels("mnist/train-labels.idx1-ubyte").unwrap();
+    train_sequence_classifier(&mut net, sequences, &labels, &mut conf, &mut rng, learning);
+  } else {
+    let all_data = mnist::load_idx_images_halved("mnist/train-images.idx3-ubyte").unwrap();
+    // the held-out split is not validated on; a full pass over it after every batch is too slow
+    let (train_data, _) = Network::split_data_sequences_autoencoder(&mut rng, all_data, &conf);
+    // let ref_mut_rng = &mut rng;
+    net.train_autoencoder(|| {
+      let idx = ::rand::seq::sample_indices(&mut rng, train_data.len(), (conf.batch_size.unwrap_or(0.01) as f32 * train_data.len() as f32) as usize);
+      Some(idx.iter().map(|&it| train_data[it].clone()).collect())
+    }, None, &conf, Some(learning));
+  }
+
+  net.save(args.value_of("output").unwrap()).unwrap();
+  println!("Model written to {}", args.value_of("output").unwrap());
+}
+
+/// Trains `net` to predict the digit labels of `inputs`, holding out a validation split for
+/// early stopping. The loss is always cross-entropy, which `attack` assumes of classifiers.
+fn train_classifier<R: rand::Rng>(net: &mut Network, inputs: Vec<Vec<f32>>, labels: &[usize], conf: &mut TrainConfig, rng: &mut R, learning: Arc<AtomicBool>) {
+  if net.output_size() != 10 {
+    panic!("a classifier needs 10 outputs, the network has {}", net.output_size());
+  }
+  let all_data = inputs.into_iter().zip(labels).map(|(input, &lbl)| (input, mnist::one_hot(lbl, 10))).collect();
+  let (train_data, validation_data) = Network::split_data_sequences(rng, all_data, conf);
+  conf.loss = Loss::CrossEntropy;
+  conf.report_accuracy = true;
+
+  let validation_data = if validation_data.is_empty() { None } else { Some(validation_data) };
+  let batch_size = (conf.batch_size.unwrap_or(0.01) as f32 * train_data.len() as f32) as usize;
+  net.train(|| {
+    let idx = ::rand::seq::sample_indices(rng, train_data.len(), batch_size);
+    Some(idx.iter().map(|&it| train_data[it].clone()).collect())
+  }, validation_data, conf, Some(learning));
+}
+
+/// Like `train_classifier`, but feeds the images to `net` one row per step.
+fn train_sequence_classifier<R: rand::Rng>(net: &mut Network, sequences: Vec<Vec<Vec<f32>>>, labels: &[usize], conf: &mut TrainConfig, rng: &mut R, learning: Arc<AtomicBool>) {
+  if net.output_size() != 10 {
+    panic!("a classifier needs 10 outputs, the network has {}", net.output_size());
+  }
+  let amt = (conf.validation_ratio * sequences.len() as f32) as usize;
+  let validation_idx = ::rand::seq::sample_indices(rng, sequences.len(), amt);
+  let (validation_data, train_data): (Vec<_>, Vec<_>) = sequences.into_iter().zip(labels).enumerate()
+    .map(|(it, (sequence, &lbl))| (it, (sequence, mnist::one_hot(lbl, 10))))
+    .partition(|&(it, _)| validation_idx.contains(&it));
+  let train_data = train_data.into_iter().map(|(_, ex)| ex).collect::<Vec<_>>();
+  conf.loss = Loss::CrossEntropy;
+  conf.report_accuracy = true;
+
+  let validation_data = if validation_data.is_empty() { None } else { Some(validation_data.into_iter().map(|(_, ex)| ex).collect()) };
+  let batch_size = (conf.batch_size.unwrap_or(0.01) as f32 * train_data.len() as f32) as usize;
+  net.train_sequences(|| {
+    let idx = ::rand::seq::sample_indices(rng, train_data.len(), batch_size);
     Some(idx.iter().map(|&it| train_data[it].clone()).collect())
-  }, None, &conf, Some(learning));
+  }, validation_data, conf, Some(learning)).unwrap_or_else(|e| panic!("the images do not fit the network: {}", e));
+}
 
-  {
+fn fine_tune<'a>(args: &ArgMatches<'a>) {
+  use rand::SeedableRng;
+
+  let learning = install_interrupt_flag();
+
+  // adversarial training in the config still applies: the classifier is fed images, as in `train`
+  let mut conf: TrainConfig = load_json_config(args.value_of("config").unwrap());
+
+  let mut rng: rand::XorShiftRng = rand::XorShiftRng::from_seed(rand::random());
+  let pretrained = Network::load(args.value_of("model").unwrap()).unwrap();
+  let mut net = pretrained.truncated(args.value_of("layer").unwrap().parse().unwrap());
+  if args.is_present("freeze") {
+    conf.frozen_layers = (0..net.layers.len()).collect();
+  }
+
+  let mut head = LayerKind::Softmax(Softmax::new(net.output_size(), 10));
+  head.assign_random_weights(&mut rng);
+  net.push_layer(head);
+  println!("classifier layer sizes: {:?}", net.layer_sizes());
+
+  let images = load_images("mnist/train-images.idx3-ubyte", net.input_size());
+  let labels = mnist::load_idx_labels("mnist/train-labels.idx1-ubyte").unwrap();
+  train_classifier(&mut net, images, &labels, &mut conf, &mut rng, learning);
+
+  net.save(args.value_of("output").unwrap()).unwrap();
+  println!("Model written to {}", args.value_of("output").unwrap());
+}
+
+/// Loads MNIST images at the resolution a model takes; 196 inputs mean halved images.
+fn load_images(path: &str, input_size: usize) -> Vec<Vec<f32>> {
+  if input_size == 196 {
+    mnist::load_idx_images_halved(path).unwrap()
+  } else {
+    mnist::load_idx_images(path).unwrap()
+  }
+}
+
+/// Loads the t10k test images and labels from `data_dir`.
+fn load_test_set(data_dir: &str, input_size: usize) -> (Vec<Vec<f32>>, Vec<usize>) {
+  use std::path::PathBuf;
+
+  let mut base_pb = PathBuf::new();
+  base_pb.push(data_dir);
+  base_pb.push("t10k-images.idx3-ubyte");
+  let images = load_images(base_pb.to_str().unwrap(), input_size);
+  base_pb.set_file_name("t10k-labels.idx1-ubyte");
+  let labels = mnist::load_idx_labels(base_pb.to_str().unwrap()).unwrap();
+
+  (images, labels)
+}
+
+fn test<'a>(args: &ArgMatches<'a>) {
+  let net = Network::load(args.value_of("model").unwrap()).unwrap();
+  let top_k = args.value_of("top_k").unwrap().parse().unwrap();
+
+  let (images, labels) = load_test_set(args.value_of("data_dir").unwrap(), net.input_size());
+
+  let outputs = images.into_iter().map(|img| net.eval(img)).collect::<Vec<_>>();
+  let report = ClassificationReport::new(&outputs, &labels, net.output_size(), top_k);
+
+  println!("accuracy: {} / {} ({:.*}%)", report.confusion.correct(), report.examples, 2, report.accuracy * 100.0);
+  println!("top-{} accuracy: {:.*}%", report.top_k, 2, report.top_k_accuracy * 100.0);
+
+  println!();
+  println!("confusion matrix (rows: actual, columns: predicted)");
+  println!("     {}", (0..report.confusion.classes()).map(|c| format!("{:>5}", c)).collect::<Vec<_>>().join(""));
+  for (actual, row) in report.confusion.counts.iter().enumerate() {
+    println!("{:>5}{}", actual, row.iter().map(|n| format!("{:>5}", n)).collect::<Vec<_>>().join(""));
+  }
+
+  println!();
+  println!("class  precision  recall      f1  support");
+  for (class, m) in report.classes.iter().enumerate() {
+    println!("{:>5}  {:>9.4}  {:>6.4}  {:>6.4}  {:>7}", class, m.precision, m.recall, m.f1, m.support);
+  }
+
+  if let Some(json_path) = args.value_of("json") {
     use std::fs::File;
-    use std::io::{Write, BufWriter};
+    sj::to_writer_pretty(File::create(json_path).unwrap(), &report).unwrap();
+    println!("Report written to {}", json_path);
+  }
+}
+
+fn probe<'a>(args: &ArgMatches<'a>) {
+  use rand::SeedableRng;
+
+  let learning = install_interrupt_flag();
+
+  let mut conf: TrainConfig = load_json_config(args.value_of("config").unwrap());
+  conf.loss = Loss::CrossEntropy;
+  conf.frozen_layers = Vec::new();
+  conf.learning_rate_multipliers = Vec::new();
+  // the probe reads codes, which perturbing and clamping to [0, 1] like images would corrupt
+  conf.adversarial = None;
 
-    let bytes = bc::serialize(&net, bc::Infinite).unwrap();
-    let mut file = BufWriter::new(File::create(args.value_of("output").unwrap()).unwrap());
-    file.write(&bytes).unwrap();
-    println!("Model written to {}", args.value_of("output").unwrap());
+  let net = Network::load(args.value_of("model").unwrap()).unwrap();
+  let layer = args.value_of("layer").unwrap().parse().unwrap();
+
+  let mut train_images = load_images("mnist/train-images.idx3-ubyte", net.input_size());
+  let mut train_labels = mnist::load_idx_labels("mnist/train-labels.idx1-ubyte").unwrap();
+  if let Some(limit) = args.value_of("limit") {
+    let limit = limit.parse().unwrap();
+    train_images.truncate(limit);
+    train_labels.truncate(limit);
   }
+  let (test_images, test_labels) = load_test_set(args.value_of("data_dir").unwrap(), net.input_size());
+
+  let train_codes = net.eval_batch_to_layer(train_images, layer);
+  let test_codes = net.eval_batch_to_layer(test_images, layer);
+  println!("probing {} features of layer {}", train_codes[0].len(), layer);
+
+  // logistic regression is a network made of a single softmax layer
+  let mut rng: rand::XorShiftRng = rand::XorShiftRng::from_seed(rand::random());
+  let mut probe = Sequential { layers: vec![LayerKind::Softmax(Softmax::new(train_codes[0].len(), 10))] };
+  probe.assign_random_weights(&mut rng);
+  train_classifier(&mut probe, train_codes.clone(), &train_labels, &mut conf, &mut rng, learning);
+
+  let outputs = probe.eval_batch(test_codes.clone());
+  let linear = ClassificationReport::new(&outputs, &test_labels, 10, 1);
+  println!("linear probe test accuracy: {:.*}%", 2, linear.accuracy * 100.0);
+
+  let knn = KNearest::new(train_codes, train_labels, args.value_of("neighbours").unwrap().parse().unwrap()).unwrap();
+  let predicted = knn.predict_all(&test_codes);
+  let correct = predicted.iter().zip(&test_labels).filter(|&(p, l)| p == l).count();
+  println!("{}-NN test accuracy: {:.*}%", knn.k, 2, correct as f32 / test_labels.len() as f32 * 100.0);
 }
 
-// fn test<'a>(args: &ArgMatches<'a>) {
-//   use nn::*;
+fn cluster<'a>(args: &ArgMatches<'a>) {
+  use std::path::PathBuf;
+  use rand::SeedableRng;
 
-//   let images = mnist::load_idx_images("mnist/train-images.idx3-ubyte").unwrap();
-//   let labels = mnist::load_idx_labels("mnist/train-labels.idx1-ubyte").unwrap();
+  let net = Network::load(args.value_of("model").unwrap()).unwrap();
+  let layer = args.value_of("layer").unwrap().parse().unwrap();
+  let k = args.value_of("clusters").unwrap().parse().unwrap();
 
-//   let test_data: Vec<(Vec<f32>, usize)> = images.into_iter().zip(labels).collect();
+  let mut images = load_images("mnist/train-images.idx3-ubyte", net.input_size());
+  let mut labels = mnist::load_idx_labels("mnist/train-labels.idx1-ubyte").unwrap();
+  if let Some(limit) = args.value_of("limit") {
+    let limit = limit.parse().unwrap();
+    images.truncate(limit);
+    labels.truncate(limit);
+  }
+  let codes = net.eval_batch_to_layer(images, layer);
 
-//   let net: Network = {
-//     use bc::serde as bcs;
-//     use std::fs::File;
-//     use std::io::BufReader;
+  let mut rng: rand::XorShiftRng = rand::XorShiftRng::from_seed(rand::random());
+  let kmeans = KMeans::fit(&mut rng, &codes, k, args.value_of("restarts").unwrap().parse().unwrap(), args.value_of("max_iterations").unwrap().parse().unwrap());
+  let assignments = kmeans.assign_all(&codes);
 
-//     let mut file = BufReader::new(File::open(args.value_of("model").unwrap()).unwrap());
-//     bcs::deserialize_from(&mut file, bc::SizeLimit::Infinite).unwrap()
-//   };
+  println!("inertia: {}", kmeans.inertia(&codes));
+  println!("purity: {}", purity(&assignments, &labels));
+  println!("normalized mutual information: {}", normalized_mutual_information(&assignments, &labels));
+  println!();
+  println!("cluster  size  labels");
+  for c in 0..k {
+    let mut histogram = vec![0; 10];
+    for (_, &l) in assignments.iter().zip(&labels).filter(|&(&a, _)| a == c) {
+      histogram[l] += 1;
+    }
+    println!("{:>7}  {:>4}  {:?}", c, histogram.iter().sum::<usize>(), histogram);
+  }
+
+  // the mean code of a cluster is its centroid
+  let decoded = if layer <= net.layers.len() {
+    net.layers_from(layer).eval_batch(kmeans.centroids.clone())
+  } else {
+    kmeans.centroids.clone()
+  };
+  let side = (decoded[0].len() as f32).sqrt() as u32;
+  if (side * side) as usize != decoded[0].len() {
+    println!("the decoder output of size {} is not a square image, not saving cluster centres", decoded[0].len());
+    return;
+  }
+  let mut base_pb = PathBuf::new();
+  base_pb.push(args.value_of("dir").unwrap());
+  for (c, out) in decoded.iter().enumerate() {
+    let bytes = out.iter().map(|x| (x.max(0.0).min(1.0) * 255.0) as u8).collect::<Vec<_>>();
+    base_pb.push(format!("cluster-{:02}.png", c));
+    img::save_buffer(base_pb.to_str().unwrap(), &bytes[..], side, side, img::ColorType::Gray(8)).unwrap();
+    base_pb.pop();
+  }
+  println!("Cluster centres written to {}", args.value_of("dir").unwrap());
+}
 
-//   let successful_predictions = test_data.iter().filter(|&&(ref example, label): &&(Vec<f32>, usize)| {
-//     use std::cmp::Ordering;
-//     let output = net.eval(na::DVector::from_slice(example.len(), &example[..]));
-//     let output_lbl = output.iter().enumerate()
-//       .max_by(|&(_, &x), &(_, &y)| if x < y { Ordering::Less } else if x > y { Ordering::Greater } else { Ordering::Equal }).unwrap_or((255, &0.0)).0;
-//     output_lbl == label
-//   }).count();
+fn evaluate<'a>(args: &ArgMatches<'a>) {
+  use std::path::PathBuf;
 
-//   for (it, case) in test_data.iter().enumerate() {
-//     use std::cmp::Ordering;
-//     let output = net.eval(na::DVector::from_slice(case.0.len(), &case.0[..]));
-//     let output_lbl = output.iter().enumerate()
-//       .max_by(|&(_, &x), &(_, &y)| if x < y { Ordering::Less } else if x > y { Ordering::Greater } else { Ordering::Equal }).unwrap_or((255, &0.0)).0;
-//     if output_lbl != case.1 {
-//       println!("misprediction: item {} as {:?}", it, output_lbl);
-//     }
-//   }
-//   let percentage = successful_predictions as f32 / test_data.len() as f32 * 100.0;
-//   println!("{} / {} ({:.*})", successful_predictions, test_data.len(), 2, percentage);
-// }
+  let net = Network::load(args.value_of("model").unwrap()).unwrap();
+  if net.input_size() != net.output_size() {
+    panic!("the model is not an autoencoder: {} inputs, {} outputs", net.input_size(), net.output_size());
+  }
 
-fn dump_features<'a>(args: &ArgMatches<'a>) {
+  let (images, labels) = load_test_set(args.value_of("data_dir").unwrap(), net.input_size());
+
+  let outputs = net.eval_batch(images.clone());
+  let errors = images.iter().zip(&outputs).map(|(img, out)| reconstruction_error(img, out)).collect::<Vec<_>>();
+
+  let mut sorted = errors.clone();
+  sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+  println!("reconstruction error over {} examples", errors.len());
+  println!("  mean: {}", errors.iter().sum::<f32>() / errors.len() as f32);
+  for &(name, p) in &[("median", 0.5), ("90th percentile", 0.9), ("95th percentile", 0.95), ("99th percentile", 0.99), ("max", 1.0)] {
+    println!("  {}: {}", name, percentile(&sorted, p));
+  }
+
+  println!();
+  println!("digit  examples  mean error");
+  for digit in 0..10 {
+    let digit_errors = errors.iter().zip(&labels).filter(|&(_, &l)| l == digit).map(|(&e, _)| e).collect::<Vec<_>>();
+    let mean = if digit_errors.is_empty() { 0.0 } else { digit_errors.iter().sum::<f32>() / digit_errors.len() as f32 };
+    println!("{:>5}  {:>8}  {}", digit, digit_errors.len(), mean);
+  }
+
+  let mut worst = (0..errors.len()).collect::<Vec<_>>();
+  worst.sort_by(|&a, &b| errors[b].partial_cmp(&errors[a]).unwrap());
+  worst.truncate(args.value_of("worst").unwrap().parse().unwrap());
+
+  let side = (net.input_size() as f32).sqrt() as u32;
+  let mut base_pb = PathBuf::new();
+  base_pb.push(args.value_of("dir").unwrap());
+  for (rank, &it) in worst.iter().enumerate() {
+    for &(suffix, ex) in &[("in", &images[it]), ("out", &outputs[it])] {
+      let bytes = ex.iter().map(|x| (x.max(0.0).min(1.0) * 255.0) as u8).collect::<Vec<_>>();
+      base_pb.push(format!("{:04}-{:05}-{}-{}.png", rank, it, labels[it], suffix));
+      img::save_buffer(base_pb.to_str().unwrap(), &bytes[..], side, side, img::ColorType::Gray(8)).unwrap();
+      base_pb.pop();
+    }
+  }
+  println!("{} worst reconstructions written to {}", worst.len(), args.value_of("dir").unwrap());
+}
+
+fn score<'a>(args: &ArgMatches<'a>) {
+  use std::fs::File;
+  use std::io::{Write, BufWriter};
+
+  let net = Network::load(args.value_of("model").unwrap()).unwrap();
+  if net.input_size() != net.output_size() {
+    panic!("the model is not an autoencoder: {} inputs, {} outputs", net.input_size(), net.output_size());
+  }
+  let scores_of = |images: Vec<Vec<f32>>| {
+    let outputs = net.eval_batch(images.clone());
+    images.iter().zip(&outputs).map(|(img, out)| reconstruction_error(img, out)).collect::<Vec<_>>()
+  };
+
+  let validation = load_dataset(args.value_of("validation").unwrap(), net.input_size()).1;
+  let threshold = calibrate_threshold(&scores_of(validation), args.value_of("false_positive_rate").unwrap().parse().unwrap());
+  let (ids, images) = load_dataset(args.value_of("data").unwrap(), net.input_size());
+  let scores = scores_of(images);
+  let flags = scores.iter().map(|&s| s > threshold).collect::<Vec<_>>();
+
+  let anomalous = args.value_of("labels").map(|path| {
+    let labels = if path.ends_with(".txt") || path.ends_with(".csv") {
+      use std::io::{BufRead, BufReader};
+      BufReader::new(File::open(path).unwrap()).lines()
+        .map(|line| line.unwrap())
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.trim().parse::<usize>().unwrap())
+        .collect::<Vec<_>>()
+    } else {
+      mnist::load_idx_labels(path).unwrap()
+    };
+    assert_eq!(labels.len(), scores.len(), "number of labels does not match number of examples");
+    let classes = args.values_of("anomaly_classes").map(|c| c.map(|c| c.parse().unwrap()).collect::<Vec<usize>>());
+    labels.into_iter().map(|l| classes.as_ref().map(|c| c.contains(&l)).unwrap_or(l != 0)).collect::<Vec<_>>()
+  });
+
+  let mut csv = BufWriter::new(File::create(args.value_of("output").unwrap()).unwrap());
+  match anomalous {
+    Some(ref anomalous) => {
+      writeln!(csv, "id,score,flagged,anomalous").unwrap();
+      for (((id, score), flag), anomaly) in ids.iter().zip(&scores).zip(&flags).zip(anomalous) {
+        writeln!(csv, "{},{},{},{}", id, score, *flag as u8, *anomaly as u8).unwrap();
+      }
+    },
+    None => {
+      writeln!(csv, "id,score,flagged").unwrap();
+      for ((id, score), flag) in ids.iter().zip(&scores).zip(&flags) {
+        writeln!(csv, "{},{},{}", id, score, *flag as u8).unwrap();
+      }
+    },
+  }
+  println!("Scores written to {}", args.value_of("output").unwrap());
+
+  println!("threshold: {}", threshold);
+  println!("flagged: {} / {}", flags.iter().filter(|&&f| f).count(), flags.len());
+  if let Some(anomalous) = anomalous {
+    let rate = |anomaly: bool| {
+      let group = flags.iter().zip(&anomalous).filter(|&(_, &a)| a == anomaly).collect::<Vec<_>>();
+      group.iter().filter(|&&(&f, _)| f).count() as f32 / group.len().max(1) as f32
+    };
+    println!("true positive rate: {}", rate(true));
+    println!("false positive rate: {}", rate(false));
+    match roc_auc(&scores, &anomalous) {
+      Some(auc) => println!("ROC-AUC: {}", auc),
+      None if anomalous.iter().all(|&a| a) => println!("ROC-AUC: n/a (every example is labelled anomalous)"),
+      None => println!("ROC-AUC: n/a (no example is labelled anomalous)"),
+    }
+  }
+}
+
+/// Loads an IDX image file, or every image file of a directory in name order, with identifiers
+/// for the examples: their indices or file names.
+fn load_dataset(path: &str, input_size: usize) -> (Vec<String>, Vec<Vec<f32>>) {
+  use std::fs;
+
+  if !fs::metadata(path).unwrap().is_dir() {
+    let images = load_images(path, input_size);
+    return ((0..images.len()).map(|it| it.to_string()).collect(), images);
+  }
+
+  let mut paths = fs::read_dir(path).unwrap().map(|entry| entry.unwrap().path()).filter(|p| p.is_file()).collect::<Vec<_>>();
+  paths.sort();
+  let side = (input_size as f32).sqrt() as usize;
+  paths.into_iter()
+    .filter_map(|p| match mnist::load_image_file(p.to_str().unwrap(), side) {
+      Ok(image) => Some((p.file_name().unwrap().to_string_lossy().into_owned(), image)),
+      Err(e) => {
+        println!("Skipping {}: {}", p.display(), e);
+        None
+      },
+    })
+    .unzip()
+}
+
+fn compare_pca<'a>(args: &ArgMatches<'a>) {
   use std::path::PathBuf;
-  use nn::*;
 
-  let net: Network = {
-    use std::fs::File;
-    use std::io::BufReader;
+  let net = Network::load(args.value_of("model").unwrap()).unwrap();
+  if net.input_size() != net.output_size() {
+    panic!("the model is not an autoencoder: {} inputs, {} outputs", net.input_size(), net.output_size());
+  }
+  let components = match args.value_of("components") {
+    Some(components) => components.parse().unwrap(),
+    None => net.layer_sizes().into_iter().min().unwrap(),
+  };
+
+  let mut train_images = load_images("mnist/train-images.idx3-ubyte", net.input_size());
+  if let Some(limit) = args.value_of("limit") {
+    train_images.truncate(limit.parse().unwrap());
+  }
+  let (test_images, _) = load_test_set(args.value_of("data_dir").unwrap(), net.input_size());
+
+  println!("fitting {} principal components to {} images", components, train_images.len());
+  let pca = Pca::fit(&train_images, components);
+  println!("explained variance: {}", pca.explained_variance_ratio());
+
+  let mean_error = |outputs: &[Vec<f32>]| test_images.iter().zip(outputs)
+    .map(|(img, out)| reconstruction_error(img, out))
+    .sum::<f32>() / test_images.len() as f32;
+  let pca_outputs = test_images.iter().map(|img| pca.reconstruct(&pca.project(img))).collect::<Vec<_>>();
+  let net_outputs = net.eval_batch(test_images.clone());
+  println!("test reconstruction error, layer sizes {:?}: {}", net.layer_sizes(), mean_error(&net_outputs));
+  println!("test reconstruction error, PCA with {} components: {}", components, mean_error(&pca_outputs));
+
+  let side = (net.input_size() as f32).sqrt() as u32;
+  let gamma = args.value_of("gamma").unwrap().parse().unwrap();
+  let mut base_pb = PathBuf::new();
+  base_pb.push(args.value_of("dir").unwrap());
+  for (it, component) in pca.components.iter().enumerate() {
+    base_pb.push(format!("component-{:04}.png", it));
+    img::save_buffer(base_pb.to_str().unwrap(), &normalized_bytes(component, gamma)[..], side, side, img::ColorType::Gray(8)).unwrap();
+    base_pb.pop();
+  }
+  println!("Principal components written to {}", args.value_of("dir").unwrap());
+}
+
+fn embed<'a>(args: &ArgMatches<'a>) {
+  use std::fs::File;
+  use std::io::{Write, BufWriter};
+  use rand::SeedableRng;
 
-    let mut file = BufReader::new(File::open(args.value_of("model").unwrap()).unwrap());
-    bc::deserialize_from(&mut file, bc::Infinite).unwrap()
+  let net = Network::load(args.value_of("model").unwrap()).unwrap();
+  let layer = args.value_of("layer").unwrap().parse().unwrap();
+
+  let (mut images, mut labels) = load_test_set(args.value_of("data_dir").unwrap(), net.input_size());
+  let amount = args.value_of("amount").unwrap().parse().unwrap();
+  images.truncate(amount);
+  labels.truncate(amount);
+  let codes = net.eval_batch_to_layer(images, layer);
+
+  let points = if args.value_of("method") == Some("pca") {
+    let pca = Pca::fit(&codes, 2);
+    println!("explained variance: {}", pca.explained_variance_ratio());
+    codes.iter().map(|code| { let p = pca.project(code); [p[0], p[1]] }).collect::<Vec<_>>()
+  } else {
+    let conf = TsneConfig {
+      perplexity: args.value_of("perplexity").unwrap().parse().unwrap(),
+      iterations: args.value_of("iterations").unwrap().parse().unwrap(),
+      ..TsneConfig::default()
+    };
+    let mut rng: rand::XorShiftRng = rand::XorShiftRng::from_seed(rand::random());
+    tsne(&mut rng, &codes, &conf)
   };
 
+  let mut csv = BufWriter::new(File::create(args.value_of("csv").unwrap()).unwrap());
+  writeln!(csv, "x,y,label").unwrap();
+  for (p, label) in points.iter().zip(&labels) {
+    writeln!(csv, "{},{},{}", p[0], p[1], label).unwrap();
+  }
+  println!("Coordinates written to {}", args.value_of("csv").unwrap());
+
+  save_scatter_plot(&points, &labels, args.value_of("plot").unwrap(), args.value_of("size").unwrap().parse().unwrap());
+}
+
+/// Draws every point as a small square coloured by its label on a white background.
+/// `size` must leave room for the margins, i.e. be at least 20.
+fn save_scatter_plot(points: &[[f32; 2]], labels: &[usize], path: &str, size: usize) {
+  const PALETTE: [[u8; 3]; 10] = [
+    [31, 119, 180], [255, 127, 14], [44, 160, 44], [214, 39, 40], [148, 103, 189],
+    [140, 86, 75], [227, 119, 194], [127, 127, 127], [188, 189, 34], [23, 190, 207],
+  ];
+  let margin = 10;
+
+  let (min_x, max_x) = points.iter().fold((std::f32::INFINITY, std::f32::NEG_INFINITY), |(lo, hi), p| (lo.min(p[0]), hi.max(p[0])));
+  let (min_y, max_y) = points.iter().fold((std::f32::INFINITY, std::f32::NEG_INFINITY), |(lo, hi), p| (lo.min(p[1]), hi.max(p[1])));
+  let scale = (size - 2 * margin) as f32 / (max_x - min_x).max(max_y - min_y).max(1e-12);
+
+  let mut bytes = vec![255u8; size * size * 3];
+  for (p, &label) in points.iter().zip(labels) {
+    let cx = margin + ((p[0] - min_x) * scale) as usize;
+    let cy = margin + ((max_y - p[1]) * scale) as usize;
+    for y in (cy - 1)..(cy + 2) {
+      for x in (cx - 1)..(cx + 2) {
+        let at = (y * size + x) * 3;
+        bytes[at..(at + 3)].copy_from_slice(&PALETTE[label % PALETTE.len()]);
+      }
+    }
+  }
+
+  img::save_buffer(path, &bytes[..], size as u32, size as u32, img::ColorType::RGB(8)).unwrap();
+  println!("Scatter plot written to {}", path);
+}
+
+fn interpolate_codes<'a>(args: &ArgMatches<'a>) {
+  let net = Network::load(args.value_of("model").unwrap()).unwrap();
+  let layer = args.value_of("layer").map(|l| l.parse().unwrap()).unwrap_or(net.bottleneck_layer());
+  let (images, _) = load_test_set(args.value_of("data_dir").unwrap(), net.input_size());
+
+  let from = images[args.value_of("from").unwrap().parse::<usize>().unwrap()].clone();
+  let to = images[args.value_of("to").unwrap().parse::<usize>().unwrap()].clone();
+  let codes = interpolate(&net.eval_to_layer(from.clone(), layer), &net.eval_to_layer(to.clone(), layer),
+    args.value_of("steps").unwrap().parse().unwrap(), args.is_present("spherical"));
+
+  let mut strip = vec![from];
+  strip.extend(net.eval_batch_from_layer(codes, layer).unwrap());
+  strip.push(to);
+  let columns = strip.len();
+  save_grid(&strip, columns, args.value_of("output").unwrap());
+}
+
+fn traverse_codes<'a>(args: &ArgMatches<'a>) {
+  let net = Network::load(args.value_of("model").unwrap()).unwrap();
+  let layer = args.value_of("layer").map(|l| l.parse().unwrap()).unwrap_or(net.bottleneck_layer());
+  let steps = args.value_of("steps").unwrap().parse().unwrap();
+  let (images, _) = load_test_set(args.value_of("data_dir").unwrap(), net.input_size());
+
+  let code = net.eval_to_layer(images[args.value_of("image").unwrap().parse::<usize>().unwrap()].clone(), layer);
+  let test_codes = net.eval_batch_to_layer(images, layer);
+
+  let mut grid = Vec::with_capacity(code.len() * steps);
+  for unit in 0..code.len() {
+    let from = test_codes.iter().map(|c| c[unit]).fold(std::f32::INFINITY, f32::min);
+    let to = test_codes.iter().map(|c| c[unit]).fold(std::f32::NEG_INFINITY, f32::max);
+    grid.extend(net.eval_batch_from_layer(traverse(&code, unit, from, to, steps), layer).unwrap());
+  }
+  save_grid(&grid, steps, args.value_of("output").unwrap());
+}
+
+/// Saves square images with values in `[0, 1]` as a grid with a 1px gap, row by row.
+fn save_grid(images: &[Vec<f32>], columns: usize, path: &str) {
+  let side = (images[0].len() as f32).sqrt() as usize;
+  let rows = (images.len() + columns - 1) / columns;
+  let (img_width, img_height) = (columns * (side + 1) - 1, rows * (side + 1) - 1);
+  let mut bytes = vec![0u8; img_width * img_height];
+
+  for (it, image) in images.iter().enumerate() {
+    let (tile_x, tile_y) = ((it % columns) * (side + 1), (it / columns) * (side + 1));
+    for (px, &x) in image.iter().enumerate() {
+      bytes[(tile_y + px / side) * img_width + tile_x + px % side] = (x.max(0.0).min(1.0) * 255.0) as u8;
+    }
+  }
+
+  img::save_buffer(path, &bytes[..], img_width as u32, img_height as u32, img::ColorType::Gray(8)).unwrap();
+  println!("Images written to {}", path);
+}
+
+/// Grayscale pixels spanning the whole range of `values`, as `dump-features` saves weights.
+fn normalized_bytes(values: &[f32], gamma: f32) -> Vec<u8> {
+  let min = values.iter().fold(std::f32::INFINITY, |acc, &x| if x < acc { x } else { acc });
+  let max = values.iter().fold(std::f32::NEG_INFINITY, |acc, &x| if x > acc { x } else { acc });
+  values.iter()
+    .map(|&x| if max > min { ((x - min) / (max - min)).powf(gamma) } else { 0.0 })
+    .map(|x| (x * 255.0) as u8)
+    .collect()
+}
+
+fn dump_features<'a>(args: &ArgMatches<'a>) {
+  use std::path::PathBuf;
+  use nn::*;
+
+  let net = Network::load(args.value_of("model").unwrap()).unwrap();
+
   let mut base_pb = PathBuf::new();
   base_pb.push(args.value_of("dir").unwrap());
 
-  for col_it in 0..net.weights[1].ncols() {
+  let weights = match net.layers[0] {
+    LayerKind::Dense(ref dense) => &dense.weights,
+    _ => panic!("the first layer of the model is not dense"),
+  };
+
+  for col_it in 0..weights.ncols() {
     use na::{Iterable, Column};
-    let col: na::DVector<f32> = net.weights[1].column(col_it);
+    let col: na::DVector<f32> = weights.column(col_it);
     let denom = col.iter().map(|x| x*x).sum::<f32>().sqrt();
 
     let min = col.iter().map(|x| x / denom).fold(std::f32::INFINITY, |acc, x| if x < acc { x } else { acc });
@@ -167,19 +695,198 @@ fn dump_features<'a>(args: &ArgMatches<'a>) {
   }
 }
 
+fn visualize<'a>(args: &ArgMatches<'a>) {
+  use std::path::PathBuf;
+  use rayon::prelude::*;
+
+  let net = Network::load(args.value_of("model").unwrap()).unwrap();
+  let layer: usize = args.value_of("layer").unwrap().parse().unwrap();
+  let units = match args.values_of("units") {
+    Some(units) => units.map(|u| u.parse().unwrap()).collect::<Vec<usize>>(),
+    None => (0..net.layer_sizes()[layer - 1]).collect(),
+  };
+  let conf = MaximizeConfig {
+    norm: args.value_of("norm").unwrap().parse().unwrap(),
+    step_size: args.value_of("step_size").unwrap().parse().unwrap(),
+    iterations: args.value_of("iterations").unwrap().parse().unwrap(),
+  };
+  let gamma = args.value_of("gamma").unwrap().parse().unwrap();
+  let side = (net.input_size() as f32).sqrt() as u32;
+
+  let inputs = units.par_iter().map(|&unit| net.maximize_activation(layer, unit, &conf)).collect::<Vec<_>>();
+
+  let mut base_pb = PathBuf::new();
+  base_pb.push(args.value_of("dir").unwrap());
+  for (&unit, (input, activation)) in units.iter().zip(inputs) {
+    println!("Unit {}: activation {}", unit, activation);
+    base_pb.push(format!("feature-{}-{:04}.png", layer - 2, unit));
+    img::save_buffer(base_pb.to_str().unwrap(), &normalized_bytes(&input, gamma), side, side, img::ColorType::Gray(8)).unwrap();
+    base_pb.pop();
+  }
+}
+
+fn saliency<'a>(args: &ArgMatches<'a>) {
+  use std::path::PathBuf;
+
+  let net = Network::load(args.value_of("model").unwrap()).unwrap();
+  let (images, labels) = load_test_set(args.value_of("data_dir").unwrap(), net.input_size());
+  let side = (net.input_size() as f32).sqrt() as usize;
+
+  let mut base_pb = PathBuf::new();
+  base_pb.push(args.value_of("dir").unwrap());
+  for index in args.values_of("images").unwrap().map(|i| i.parse::<usize>().unwrap()) {
+    let image = &images[index];
+    let predicted = argmax(&net.eval(image.clone()));
+    let class = args.value_of("class").map(|c| c.parse().unwrap()).unwrap_or(predicted);
+    let gradient = net.unit_input_gradient(image.clone(), net.layers.len() + 1, class).1;
+    println!("Image {}: label {}, predicted {}, explaining {}", index, labels[index], predicted, class);
+
+    let max = gradient.iter().fold(0.0f32, |acc, g| acc.max(g.abs())).max(1e-12);
+    let mut bytes = vec![0u8; side * (2 * side + 1) * 3];
+    for (px, (&x, g)) in image.iter().zip(&gradient).enumerate() {
+      let row = (px / side) * (2 * side + 1);
+      let gray = (x.max(0.0).min(1.0) * 255.0) as u8;
+      let at = (row + px % side) * 3;
+      bytes[at..(at + 3)].copy_from_slice(&[gray, gray, gray]);
+      let at = (row + side + 1 + px % side) * 3;
+      bytes[at..(at + 3)].copy_from_slice(&heat(g.abs() / max));
+    }
+
+    base_pb.push(format!("saliency-{:05}.png", index));
+    img::save_buffer(base_pb.to_str().unwrap(), &bytes[..], (2 * side + 1) as u32, side as u32, img::ColorType::RGB(8)).unwrap();
+    base_pb.pop();
+  }
+}
+
+/// Black through red and yellow to white as `x` goes from 0 to 1.
+fn heat(x: f32) -> [u8; 3] {
+  let channel = |offset: f32| ((3.0 * x - offset).max(0.0).min(1.0) * 255.0) as u8;
+  [channel(0.0), channel(1.0), channel(2.0)]
+}
+
+fn attack<'a>(args: &ArgMatches<'a>) {
+  use std::path::PathBuf;
+  use rayon::prelude::*;
+
+  let net = Network::load(args.value_of("model").unwrap()).unwrap();
+  let conf = AttackConfig {
+    method: if args.value_of("method") == Some("fgsm") { AttackMethod::Fgsm } else { AttackMethod::Pgd },
+    epsilon: args.value_of("epsilon").unwrap().parse().unwrap(),
+    steps: args.value_of("steps").unwrap().parse().unwrap(),
+    step_size: args.value_of("step_size").map(|s| s.parse().unwrap()),
+  };
+
+  let (mut images, mut labels) = load_test_set(args.value_of("data_dir").unwrap(), net.input_size());
+  if let Some(limit) = args.value_of("limit").map(|l| l.parse().unwrap()) {
+    images.truncate(limit);
+    labels.truncate(limit);
+  }
+
+  // classifiers are trained with cross-entropy on a softmax output
+  let results = images.par_iter().zip(labels.par_iter())
+    .map(|(image, &label)| {
+      let adversarial = net.adversarial_example(image, &mnist::one_hot(label, net.output_size()), Loss::CrossEntropy, &conf);
+      let clean = argmax(&net.eval(image.clone()));
+      let attacked = argmax(&net.eval(adversarial.clone()));
+      (clean, attacked, adversarial)
+    })
+    .collect::<Vec<_>>();
+
+  let clean_correct = results.iter().zip(&labels).filter(|&(r, &label)| r.0 == label).count();
+  let robust_correct = results.iter().zip(&labels).filter(|&(r, &label)| r.1 == label).count();
+  println!("clean accuracy: {} / {} ({:.*}%)", clean_correct, labels.len(), 2, clean_correct as f32 / labels.len() as f32 * 100.0);
+  println!("robust accuracy: {} / {} ({:.*}%)", robust_correct, labels.len(), 2, robust_correct as f32 / labels.len() as f32 * 100.0);
+
+  let mut base_pb = PathBuf::new();
+  base_pb.push(args.value_of("dir").unwrap());
+  let save = args.value_of("save").unwrap().parse().unwrap();
+  let flipped = results.iter().enumerate().filter(|&(it, r)| r.0 == labels[it] && r.1 != labels[it]);
+  for (it, &(_, attacked, ref adversarial)) in flipped.take(save) {
+    base_pb.push(format!("adversarial-{:05}-{}-as-{}.png", it, labels[it], attacked));
+    save_grid(&[images[it].clone(), adversarial.clone()], 2, base_pb.to_str().unwrap());
+    base_pb.pop();
+  }
+}
+
+fn inpaint<'a>(args: &ArgMatches<'a>) {
+  use std::path::PathBuf;
+  use rand::SeedableRng;
+
+  let net = Network::load(args.value_of("model").unwrap()).unwrap();
+  let (images, _) = load_test_set(args.value_of("data_dir").unwrap(), net.input_size());
+  let image = &images[args.value_of("image").unwrap().parse::<usize>().unwrap()];
+  let side = (net.input_size() as f32).sqrt() as usize;
+
+  let known = match args.value_of("mask") {
+    Some(path) => {
+      let mask = img::open(path).unwrap().to_luma();
+      assert_eq!((side as u32, side as u32), mask.dimensions(), "mask size does not match the model's images");
+      mask.into_raw().into_iter().map(|p| p < 128).collect::<Vec<_>>()
+    },
+    None => {
+      let mut rng: rand::XorShiftRng = rand::XorShiftRng::from_seed(rand::random());
+      let size = args.value_of("square").map(|s| s.parse().unwrap()).unwrap_or(side / 2);
+      random_square_mask(&mut rng, side, size)
+    },
+  };
+
+  let iterates = net.inpaint(image, &known, args.value_of("iterations").unwrap().parse().unwrap(),
+    args.value_of("tolerance").unwrap().parse().unwrap());
+
+  let mut base_pb = PathBuf::new();
+  base_pb.push(args.value_of("dir").unwrap());
+  for (it, iterate) in iterates.iter().enumerate() {
+    let bytes = iterate.iter().map(|x| (x.max(0.0).min(1.0) * 255.0) as u8).collect::<Vec<_>>();
+    base_pb.push(format!("inpaint-{:03}.png", it));
+    img::save_buffer(base_pb.to_str().unwrap(), &bytes[..], side as u32, side as u32, img::ColorType::Gray(8)).unwrap();
+    base_pb.pop();
+  }
+
+  println!("passes: {}", iterates.len() - 1);
+  println!("occluded pixels: {}", known.iter().filter(|&&k| !k).count());
+  println!("initial error on occluded pixels: {}", masked_reconstruction_error(image, &iterates[0], &known));
+  println!("final error on occluded pixels: {}", masked_reconstruction_error(image, &iterates[iterates.len() - 1], &known));
+}
+
+fn denoise<'a>(args: &ArgMatches<'a>) {
+  use std::path::{Path, PathBuf};
+  use rand::SeedableRng;
+  use rand::distributions::{Normal, IndependentSample};
+
+  let net = Network::load(args.value_of("model").unwrap()).unwrap();
+  let side = (net.input_size() as f32).sqrt() as usize;
+  let noise = args.value_of("noise").map(|n| Normal::new(0.0, n.parse().unwrap()));
+  let mut rng: rand::XorShiftRng = rand::XorShiftRng::from_seed(rand::random());
+
+  let mut base_pb = PathBuf::new();
+  base_pb.push(args.value_of("dir").unwrap());
+  for path in args.values_of("images").unwrap() {
+    let image = mnist::load_image_file(path, side).unwrap();
+    let mut strip = vec![image.clone()];
+    let input = match noise {
+      Some(dist) => {
+        let noisy = image.iter().map(|&x| (x + dist.ind_sample(&mut rng) as f32).max(0.0).min(1.0)).collect::<Vec<_>>();
+        strip.push(noisy.clone());
+        noisy
+      },
+      None => image,
+    };
+    strip.push(net.eval(input));
+
+    base_pb.push(format!("{}.png", Path::new(path).file_stem().unwrap().to_string_lossy()));
+    let columns = strip.len();
+    save_grid(&strip, columns, base_pb.to_str().unwrap());
+    base_pb.pop();
+  }
+}
+
 fn sample<'a>(args: &ArgMatches<'a>) {
   use std::path::PathBuf;
   use nn::*;
   use rand::SeedableRng;
   use rand::seq::sample_iter;
 
-  let net: Network = {
-    use std::fs::File;
-    use std::io::BufReader;
-
-    let mut file = BufReader::new(File::open(args.value_of("model").unwrap()).unwrap());
-    bc::deserialize_from(&mut file, bc::Infinite).unwrap()
-  };
+  let net = Network::load(args.value_of("model").unwrap()).unwrap();
 
   let mut base_pb = PathBuf::new();
   base_pb.push(args.value_of("dir").unwrap());
@@ -193,10 +900,133 @@ fn sample<'a>(args: &ArgMatches<'a>) {
     img::save_buffer(base_pb.to_str().unwrap(), &bytes_ex[..], 14, 14, img::ColorType::Gray(8)).unwrap();
     base_pb.pop();
 
-    let out = net.eval(na::DVector::from_slice(ex.len(), &ex[..]));
+    let out = net.eval(ex.clone());
     let bytes_enc = out.iter().map(|x| (x * 255.0) as u8).collect::<Vec<_>>();
     base_pb.push(format!("{:04}-out.png", it));
     img::save_buffer(base_pb.to_str().unwrap(), &bytes_enc[..], 14, 14, img::ColorType::Gray(8)).unwrap();
     base_pb.pop();
   }
-}
\ No newline at end of file
+}
+
+fn som<'a>(args: &ArgMatches<'a>) {
+  use rand::SeedableRng;
+
+  let learning = install_interrupt_flag();
+
+  let conf: SomConfig = load_json_config(args.value_of("config").unwrap());
+  conf.validate().unwrap_or_else(|e| panic!("invalid SOM config: {}", e));
+
+  let data = mnist::load_idx_images_halved("mnist/train-images.idx3-ubyte").unwrap();
+
+  let mut rng: rand::XorShiftRng = rand::XorShiftRng::from_seed(rand::random());
+  let mut som = if let Some(model_path) = args.value_of("model") {
+    Som::load(model_path).unwrap()
+  } else {
+    let mut som = Som::new(conf.width, conf.height, data[0].len());
+    som.assign_random_weights(&mut rng, &data);
+    som
+  };
+  som.train(&mut rng, &data, &conf, Some(learning));
+
+  println!("quantization err: {}, topographic err: {}", som.quantization_error(&data), som.topographic_error(&data));
+
+  som.save(args.value_of("output").unwrap()).unwrap();
+  println!("Map written to {}", args.value_of("output").unwrap());
+
+  save_codebook(&som, args.value_of("codebook").unwrap(), args.value_of("gamma").unwrap().parse().unwrap());
+}
+
+/// Saves every codebook vector as a square tile, laid out like the map's grid with a 1px gap.
+fn save_codebook(som: &Som, path: &str, gamma: f32) {
+  let side = (som.input_size() as f32).sqrt() as usize;
+  let (img_width, img_height) = (som.width * (side + 1) - 1, som.height * (side + 1) - 1);
+  let mut bytes = vec![0u8; img_width * img_height];
+
+  for (it, unit) in som.codebook.iter().enumerate() {
+    let (tile_x, tile_y) = ((it % som.width) * (side + 1), (it / som.width) * (side + 1));
+
+    for (px, value) in normalized_bytes(&unit.at, gamma).into_iter().enumerate() {
+      bytes[(tile_y + px / side) * img_width + tile_x + px % side] = value;
+    }
+  }
+
+  img::save_buffer(path, &bytes[..], img_width as u32, img_height as u32, img::ColorType::Gray(8)).unwrap();
+  println!("Codebook written to {}", path);
+}
+
+fn model_edit<'a>(args: &ArgMatches<'a>) {
+  use rand::SeedableRng;
+
+  fn layer_and<T: std::str::FromStr>(arg: &str) -> (usize, T) {
+    let mut parts = arg.splitn(2, ':');
+    match (parts.next().and_then(|l| l.parse().ok()), parts.next().and_then(|v| v.parse().ok())) {
+      (Some(layer), Some(value)) => (layer, value),
+      _ => panic!("expected LAYER:VALUE, got {}", arg),
+    }
+  }
+
+  let mut net = Network::load(args.value_of("model").unwrap()).unwrap();
+  println!("layer sizes before: {:?}", net.layer_sizes());
+
+  let edited = if let Some(arg) = args.value_of("add_units") {
+    let (layer, count) = layer_and(arg);
+    let mut rng: rand::XorShiftRng = rand::XorShiftRng::from_seed(rand::random());
+    net.add_units(layer, count, &mut rng).map(|_| net)
+  } else if let Some(arg) = args.value_of("prune") {
+    let (layer, min_norm) = layer_and(arg);
+    net.prune_units(layer, min_norm).map(|removed| {
+      println!("removed {} units", removed);
+      net
+    })
+  } else if let Some(arg) = args.value_of("insert_identity") {
+    net.insert_identity_layer(arg.parse().unwrap()).map(|_| net)
+  } else {
+    net.concat(Network::load(args.value_of("append").unwrap()).unwrap())
+  };
+  let net = edited.unwrap_or_else(|e| panic!("cannot edit the model: {}", e));
+
+  println!("layer sizes after: {:?}", net.layer_sizes());
+  net.save(args.value_of("output").unwrap()).unwrap();
+  println!("Model written to {}", args.value_of("output").unwrap());
+}
+
+fn inspect<'a>(args: &ArgMatches<'a>) {
+  let net = Network::load(args.value_of("model").unwrap()).unwrap();
+
+  println!("layer sizes: {:?}", net.layer_sizes());
+  // numbered by the layer of units each one produces, as the `--layer` flags count (1 is the input)
+  for (it, layer) in net.layers.iter().enumerate() {
+    print_layer(&format!("layer {}", it + 2), layer, "");
+  }
+}
+
+fn print_layer(name: &str, layer: &LayerKind, indent: &str) {
+  match layer {
+    &LayerKind::Dense(ref dense) => {
+      println!("{}{}: dense {} -> {}, {:?}{}", indent, name, dense.input_size(), dense.output_size(), dense.activation_fn,
+        if dense.batch_norm.is_some() { ", batch norm" } else { "" });
+      println!("{}  activation coeffs ({}): {:?}", indent, if dense.learn_coeffs { "learned" } else { "fixed" }, dense.activation_coeffs.at);
+    },
+    &LayerKind::Maxout(ref maxout) => {
+      println!("{}{}: maxout {} -> {}, {} pieces", indent, name, maxout.input_size(), maxout.output_size(), maxout.pieces);
+    },
+    &LayerKind::Recurrent(ref recurrent) => {
+      println!("{}{}: {:?} recurrent {} -> {}, {} steps of {}", indent, name, recurrent.cell, recurrent.input_size(), recurrent.output_size(), recurrent.steps, recurrent.step_size());
+    },
+    &LayerKind::Softmax(ref softmax) => {
+      println!("{}{}: softmax {} -> {}", indent, name, softmax.input_size(), softmax.output_size());
+    },
+    &LayerKind::Graph(ref graph) => {
+      println!("{}{}: graph {} -> {}", indent, name, graph.input_size(), graph.output_size());
+      for node in &graph.nodes {
+        let inputs = node.inputs.iter()
+          .map(|&i| if i == 0 { "input" } else { graph.nodes[i - 1].name.as_str() })
+          .collect::<Vec<_>>();
+        println!("{}  node {}: {:?} of [{}]", indent, node.name, node.merge, inputs.join(", "));
+        if let Some(ref layer) = node.layer {
+          print_layer(&node.name, layer, &format!("{}    ", indent));
+        }
+      }
+    },
+  }
+}
@@ -0,0 +1,69 @@
+//! Finite-difference checks of the gradients layers compute in `backward`, shared by the layer
+//! modules' tests.
+
+use rand::{Rng, SeedableRng, XorShiftRng};
+
+use na::DVector;
+
+use layer::{Layer, LayerKind};
+use nn::Network;
+
+/// The layers of the network defined by `json`, without weights.
+pub fn layers(json: &str) -> Vec<LayerKind> {
+  Network::from_definition(&::sj::from_str(json).unwrap()).unwrap().layers
+}
+
+/// Assigns random weights to `layer` and checks its gradients over a batch of `batch` examples.
+pub fn check(mut layer: LayerKind, batch: usize) {
+  layer.assign_random_weights(&mut XorShiftRng::from_seed([1, 2, 3, 4]));
+  check_weighted(layer, batch);
+}
+
+/// Compares the input and parameter gradients of `layer` with central differences of the loss
+/// `sum(output * r)` for random inputs and random `r`.
+pub fn check_weighted(mut layer: LayerKind, batch: usize) {
+  let mut rng = XorShiftRng::from_seed([5, 2, 3, 4]);
+  let inputs = (0..batch).map(|_| DVector::from_fn(layer.input_size(), |_| rng.gen_range(-1.0, 1.0))).collect::<Vec<_>>();
+  let r = (0..batch).map(|_| DVector::from_fn(layer.output_size(), |_| rng.gen_range(-1.0, 1.0))).collect::<Vec<_>>();
+  let (_, cache) = layer.forward(&inputs, true);
+  let (input_deltas, updates) = layer.backward(&cache, &r);
+
+  for ex in 0..batch {
+    for i in 0..layer.input_size() {
+      let mut plus = inputs.clone();
+      plus[ex].at[i] += EPSILON;
+      let mut minus = inputs.clone();
+      minus[ex].at[i] -= EPSILON;
+      let numeric = (loss(&layer, &plus, &r) - loss(&layer, &minus, &r)) / (2.0 * EPSILON as f64);
+      assert_close(numeric, input_deltas[ex][i], &format!("input {} of example {}", i, ex));
+    }
+  }
+
+  assert_eq!(updates.len(), layer.params().len());
+  for p in 0..updates.len() {
+    assert_eq!(updates[p].len(), layer.params()[p].len());
+    for j in 0..updates[p].len() {
+      let orig = layer.params()[p][j];
+      layer.params_mut()[p][j] = orig + EPSILON;
+      let plus = loss(&layer, &inputs, &r);
+      layer.params_mut()[p][j] = orig - EPSILON;
+      let minus = loss(&layer, &inputs, &r);
+      layer.params_mut()[p][j] = orig;
+      let numeric = (plus - minus) / (2.0 * EPSILON as f64);
+      assert_close(numeric, updates[p][j], &format!("parameter {} of group {}", j, p));
+    }
+  }
+}
+
+const EPSILON: f32 = 1e-3;
+
+fn loss(layer: &LayerKind, inputs: &[DVector<f32>], r: &[DVector<f32>]) -> f64 {
+  layer.forward(inputs, true).0.iter().zip(r)
+    .map(|(out, r)| out.at.iter().zip(&r.at).map(|(o, r)| (o * r) as f64).sum::<f64>())
+    .sum()
+}
+
+fn assert_close(numeric: f64, analytic: f32, what: &str) {
+  let error = (numeric - analytic as f64).abs() / (1.0 + numeric.abs());
+  assert!(error < 2e-3, "gradient of {}: {} numerically, {} by backpropagation", what, numeric, analytic);
+}
@@ -0,0 +1,338 @@
+use std::collections::HashMap;
+
+use rand::Rng;
+
+use na::DVector;
+
+use layer::{Layer, LayerKind, LayerCache};
+use nn::{NetworkDefn, DefnError};
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum Merge {
+  Sum,
+  Concat,
+}
+
+impl Merge {
+  pub fn from_name(name: &str) -> Option<Merge> {
+    match name {
+      "sum" => Some(Merge::Sum),
+      "concat" => Some(Merge::Concat),
+      _ => None,
+    }
+  }
+
+  fn apply(&self, parts: &[&DVector<f32>]) -> DVector<f32> {
+    match self {
+      &Merge::Sum => {
+        let mut sum = parts[0].clone();
+        for &part in &parts[1..] {
+          sum += part.clone();
+        }
+        sum
+      },
+      &Merge::Concat => DVector { at: parts.iter().flat_map(|p| p.at.iter().cloned()).collect() },
+    }
+  }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Node {
+  pub name: String,
+  /// Value indices: 0 is the graph input, `k` is the output of `nodes[k - 1]`.
+  pub inputs: Vec<usize>,
+  pub merge: Merge,
+  /// Merge-only nodes (e.g. the sum closing a residual block) have no layer.
+  pub layer: Option<LayerKind>,
+  pub size: usize,
+}
+
+/// A layer made of named nodes, each merging the outputs of earlier nodes and optionally
+/// passing the result through its own layer. Nodes are stored in topological order.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Graph {
+  pub input_size: usize,
+  pub nodes: Vec<Node>,
+  pub output: usize,
+}
+
+pub struct GraphCache {
+  node_caches: Vec<Option<LayerCache>>,
+}
+
+impl Graph {
+  /// Builds the graph declared by the nodes of `network`; the last declared node is the output.
+  pub fn from_definition(input_size: usize, network: &NetworkDefn) -> Result<Graph, DefnError> {
+    let defns = &network.nodes;
+    let mut by_name = HashMap::new();
+    for (it, defn) in defns.iter().enumerate() {
+      if defn.name == "input" || by_name.insert(defn.name.as_str(), it).is_some() {
+        return Err(DefnError::DuplicateNode(defn.name.clone()));
+      }
+    }
+
+    // declared index of every node input, `None` standing for the graph input
+    let mut sources = Vec::with_capacity(defns.len());
+    for defn in defns {
+      if defn.inputs.is_empty() {
+        return Err(DefnError::NoInputs(defn.name.clone()));
+      }
+      let mut node_sources = Vec::with_capacity(defn.inputs.len());
+      for input in &defn.inputs {
+        node_sources.push(match by_name.get(input.as_str()) {
+          Some(&it) => Some(it),
+          None if input == "input" => None,
+          None => return Err(DefnError::UnknownNode { node: defn.name.clone(), input: input.clone() }),
+        });
+      }
+      sources.push(node_sources);
+    }
+
+    // Kahn's algorithm; whatever is left unordered lies on or behind a cycle
+    let mut pending = sources.iter().map(|s| s.iter().filter(|s| s.is_some()).count()).collect::<Vec<_>>();
+    let mut ready = (0..defns.len()).filter(|&it| pending[it] == 0).collect::<Vec<_>>();
+    let mut order = Vec::with_capacity(defns.len());
+    while let Some(it) = ready.pop() {
+      order.push(it);
+      for (dep, node_sources) in sources.iter().enumerate() {
+        for _ in node_sources.iter().filter(|&&s| s == Some(it)) {
+          pending[dep] -= 1;
+          if pending[dep] == 0 {
+            ready.push(dep);
+          }
+        }
+      }
+    }
+    if order.len() < defns.len() {
+      // drop the nodes that are merely downstream of a cycle
+      let mut stuck = (0..defns.len()).filter(|it| !order.contains(it)).collect::<Vec<_>>();
+      loop {
+        let feeding = stuck.iter().cloned()
+          .filter(|&it| stuck.iter().any(|&dep| sources[dep].contains(&Some(it))))
+          .collect::<Vec<_>>();
+        if feeding.len() == stuck.len() {
+          break;
+        }
+        stuck = feeding;
+      }
+      return Err(DefnError::Cycle(stuck.into_iter().map(|it| defns[it].name.clone()).collect()));
+    }
+
+    let mut value_of = vec![0; defns.len()];
+    let mut sizes = vec![input_size];
+    let mut nodes = Vec::with_capacity(defns.len());
+    for &it in &order {
+      let defn = &defns[it];
+      let merge = Merge::from_name(&defn.merge)
+        .ok_or_else(|| DefnError::UnknownMerge { node: defn.name.clone(), merge: defn.merge.clone() })?;
+      let inputs = sources[it].iter().map(|s| s.map(|s| value_of[s]).unwrap_or(0)).collect::<Vec<_>>();
+
+      let merged_size = match merge {
+        Merge::Sum => {
+          let expected = sizes[inputs[0]];
+          if let Some(&found) = inputs.iter().map(|&i| &sizes[i]).find(|&&s| s != expected) {
+            return Err(DefnError::SizeMismatch { node: defn.name.clone(), expected: expected, found: found });
+          }
+          expected
+        },
+        Merge::Concat => inputs.iter().map(|&i| sizes[i]).sum(),
+      };
+      let layer = match defn.size {
+        Some(size) => Some(network.layer(&defn.layer_type, merged_size, size, defn.activation_coeff, defn.batch_norm)?),
+        None => None,
+      };
+      let size = layer.as_ref().map(|l| l.output_size()).unwrap_or(merged_size);
+
+      value_of[it] = nodes.len() + 1;
+      sizes.push(size);
+      nodes.push(Node {
+        name: defn.name.clone(),
+        inputs: inputs,
+        merge: merge,
+        layer: layer,
+        size: size,
+      });
+    }
+
+    Ok(Graph {
+      input_size: input_size,
+      nodes: nodes,
+      output: value_of[defns.len() - 1],
+    })
+  }
+
+  fn value_size(&self, value: usize) -> usize {
+    if value == 0 { self.input_size } else { self.nodes[value - 1].size }
+  }
+
+  fn layers(&self) -> Vec<&LayerKind> {
+    self.nodes.iter().filter_map(|n| n.layer.as_ref()).collect()
+  }
+
+  /// Walks the nodes in reverse topological order, collecting the parameter gradients of every
+  /// node into `updates` if given.
+  fn backpropagate(&self, cache: &GraphCache, output_deltas: &[DVector<f32>], mut updates: Option<&mut Vec<Vec<Vec<f32>>>>) -> Vec<DVector<f32>> {
+    let mut deltas: Vec<Option<Vec<DVector<f32>>>> = vec![None; self.nodes.len() + 1];
+    deltas[self.output] = Some(output_deltas.to_vec());
+
+    for (it, node) in self.nodes.iter().enumerate().rev() {
+      let node_delta = deltas[it + 1].take();
+      let merged_delta = match (node_delta, &node.layer, &cache.node_caches[it]) {
+        (Some(delta), &Some(ref layer), &Some(ref c)) => match updates {
+          Some(ref mut updates) => {
+            let (merged_delta, update) = layer.backward(c, &delta);
+            updates[it] = update;
+            Some(merged_delta)
+          },
+          None => Some(layer.backward_inputs(c, &delta)),
+        },
+        (None, &Some(ref layer), _) => {
+          // the node does not feed the output, so its parameters get no gradient
+          if let Some(ref mut updates) = updates {
+            updates[it] = layer.params().iter().map(|p| vec![0.0; p.len()]).collect();
+          }
+          None
+        },
+        (delta, _, _) => delta,
+      };
+
+      if let Some(merged_delta) = merged_delta {
+        let mut offset = 0;
+        for &src in &node.inputs {
+          let size = self.value_size(src);
+          let part = merged_delta.iter()
+            .map(|d| match node.merge {
+              Merge::Sum => d.clone(),
+              Merge::Concat => DVector { at: d.at[offset..(offset + size)].to_vec() },
+            })
+            .collect::<Vec<_>>();
+          offset += size;
+
+          deltas[src] = Some(match deltas[src].take() {
+            Some(mut acc) => {
+              for (a, p) in acc.iter_mut().zip(part) {
+                *a += p;
+              }
+              acc
+            },
+            None => part,
+          });
+        }
+      }
+    }
+
+    deltas[0].take().unwrap_or_else(|| vec![DVector::new_zeros(self.input_size); output_deltas.len()])
+  }
+}
+
+impl Layer for Graph {
+  type Cache = GraphCache;
+
+  fn input_size(&self) -> usize {
+    self.input_size
+  }
+
+  fn output_size(&self) -> usize {
+    self.value_size(self.output)
+  }
+
+  fn forward(&self, inputs: &[DVector<f32>], train: bool) -> (Vec<DVector<f32>>, GraphCache) {
+    let mut values = vec![inputs.to_vec()];
+    let mut node_caches = Vec::with_capacity(self.nodes.len());
+
+    for node in &self.nodes {
+      let merged = (0..inputs.len())
+        .map(|ex| node.merge.apply(&node.inputs.iter().map(|&i| &values[i][ex]).collect::<Vec<_>>()))
+        .collect::<Vec<_>>();
+      match node.layer {
+        Some(ref layer) => {
+          let (out, cache) = layer.forward(&merged, train);
+          node_caches.push(Some(cache));
+          values.push(out);
+        },
+        None => {
+          node_caches.push(None);
+          values.push(merged);
+        },
+      }
+    }
+
+    (values.swap_remove(self.output), GraphCache { node_caches: node_caches })
+  }
+
+  fn backward(&self, cache: &GraphCache, output_deltas: &[DVector<f32>]) -> (Vec<DVector<f32>>, Vec<Vec<f32>>) {
+    let mut updates = vec![Vec::new(); self.nodes.len()];
+    let input_deltas = self.backpropagate(cache, output_deltas, Some(&mut updates));
+    (input_deltas, updates.into_iter().flat_map(|u| u).collect())
+  }
+
+  fn backward_inputs(&self, cache: &GraphCache, output_deltas: &[DVector<f32>]) -> Vec<DVector<f32>> {
+    self.backpropagate(cache, output_deltas, None)
+  }
+
+  fn params(&self) -> Vec<&[f32]> {
+    self.layers().into_iter().flat_map(|l| l.params()).collect()
+  }
+
+  fn params_mut(&mut self) -> Vec<&mut [f32]> {
+    self.nodes.iter_mut().filter_map(|n| n.layer.as_mut()).flat_map(|l| l.params_mut()).collect()
+  }
+
+  fn decays(&self, mut idx: usize) -> bool {
+    for layer in self.layers() {
+      let count = layer.params().len();
+      if idx < count {
+        return layer.decays(idx);
+      }
+      idx -= count;
+    }
+    false
+  }
+
+  fn assign_random_weights<R: Rng>(&mut self, rng: &mut R) {
+    for layer in self.nodes.iter_mut().filter_map(|n| n.layer.as_mut()) {
+      layer.assign_random_weights(rng);
+    }
+  }
+
+  fn update_statistics(&mut self, cache: &GraphCache) {
+    for (node, c) in self.nodes.iter_mut().zip(&cache.node_caches) {
+      if let (&mut Some(ref mut layer), &Some(ref c)) = (&mut node.layer, c) {
+        layer.update_statistics(c);
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use gradient_check::{check, layers};
+  use nn::{Network, DefnError};
+
+  #[test]
+  fn graph_gradients() {
+    // a residual block, a node that does not feed the output and a concat with the input
+    check(layers(r#"{"layers": [5], "activation_coeffs": [], "activation_fn": "sigmoid", "nodes": [
+      {"name": "h1", "inputs": ["input"], "size": 4},
+      {"name": "h2", "inputs": ["h1"], "size": 4},
+      {"name": "dead", "inputs": ["h1"], "size": 2},
+      {"name": "res", "inputs": ["h1", "h2"]},
+      {"name": "out", "inputs": ["res", "input"], "merge": "concat", "size": 3}]}"#).remove(0), 2);
+  }
+
+  #[test]
+  fn rejects_cycles() {
+    let defn = ::sj::from_str(r#"{"layers": [5], "activation_coeffs": [], "activation_fn": "tanh", "nodes": [
+      {"name": "a", "inputs": ["input", "c"], "size": 4},
+      {"name": "b", "inputs": ["a"], "size": 4},
+      {"name": "c", "inputs": ["b"], "size": 4},
+      {"name": "out", "inputs": ["c"], "size": 3}]}"#).unwrap();
+    match Network::from_definition(&defn) {
+      Err(DefnError::Cycle(mut nodes)) => {
+        nodes.sort();
+        assert_eq!(nodes, vec!["a", "b", "c"]);
+      },
+      other => panic!("unexpected result {:?}", other.map(|_| ())),
+    }
+  }
+}
@@ -0,0 +1,39 @@
+use rand::Rng;
+
+use nn::Sequential;
+
+impl Sequential {
+  /// Fills the pixels of `image` where `known` is false by repeatedly running the autoencoder and
+  /// taking its reconstruction of them, while known pixels stay clamped. Unknown pixels start at
+  /// 0. Returns every iterate, the starting image first, and stops once no unknown pixel changes
+  /// by more than `tolerance`.
+  pub fn inpaint(&self, image: &[f32], known: &[bool], max_iterations: usize, tolerance: f32) -> Vec<Vec<f32>> {
+    assert_eq!(self.input_size(), self.output_size());
+    let mut iterates = vec![image.iter().zip(known).map(|(&x, &k)| if k { x } else { 0.0 }).collect::<Vec<_>>()];
+
+    for _ in 0..max_iterations {
+      let current = iterates[iterates.len() - 1].clone();
+      let reconstruction = self.eval(current.clone());
+      let next = current.iter().zip(reconstruction).zip(known).map(|((&x, r), &k)| if k { x } else { r }).collect::<Vec<_>>();
+      let change = current.iter().zip(&next).map(|(a, b)| (a - b).abs()).fold(0.0, f32::max);
+
+      iterates.push(next);
+      if change <= tolerance {
+        break;
+      }
+    }
+    iterates
+  }
+}
+
+/// Known-pixel mask of a `side` x `side` image occluded by a randomly placed square.
+pub fn random_square_mask<R: Rng>(rng: &mut R, side: usize, size: usize) -> Vec<bool> {
+  assert!(size <= side, "cannot place a square of size {} in an image of size {}", size, side);
+  let (left, top) = (rng.gen_range(0, side - size + 1), rng.gen_range(0, side - size + 1));
+  (0..(side * side))
+    .map(|px| {
+      let (x, y) = (px % side, px / side);
+      !(x >= left && x < left + size && y >= top && y < top + size)
+    })
+    .collect()
+}
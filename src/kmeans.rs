@@ -0,0 +1,106 @@
+use rand::{Rng, SeedableRng, XorShiftRng};
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct KMeans {
+  pub centroids: Vec<Vec<f32>>,
+}
+
+fn distance_squared(a: &[f32], b: &[f32]) -> f32 {
+  a.iter().zip(b).map(|(x, y)| (x - y) * (x - y)).sum()
+}
+
+impl KMeans {
+  /// Runs Lloyd's algorithm from `restarts` k-means++ initialisations in parallel and keeps the
+  /// clustering with the lowest inertia.
+  pub fn fit<R: Rng>(rng: &mut R, data: &[Vec<f32>], k: usize, restarts: usize, max_iterations: usize) -> KMeans {
+    use rayon::prelude::*;
+
+    assert!(k > 0 && k <= data.len(), "cannot make {} clusters of {} points", k, data.len());
+    let seeds = (0..restarts.max(1)).map(|_| rng.gen::<[u32; 4]>()).collect::<Vec<_>>();
+
+    seeds.into_par_iter()
+      .map(|seed| {
+        let mut rng = XorShiftRng::from_seed(seed);
+        let mut kmeans = KMeans::init_plus_plus(&mut rng, data, k);
+        kmeans.lloyd(data, max_iterations);
+        let inertia = kmeans.inertia(data);
+        (inertia, kmeans)
+      })
+      .collect::<Vec<_>>()
+      .into_iter()
+      .fold(None, |best: Option<(f32, KMeans)>, (inertia, kmeans)| match best {
+        Some((best_inertia, _)) if best_inertia <= inertia => best,
+        _ => Some((inertia, kmeans)),
+      })
+      .unwrap().1
+  }
+
+  /// Picks every next centroid with probability proportional to its squared distance from the
+  /// nearest centroid chosen so far.
+  fn init_plus_plus<R: Rng>(rng: &mut R, data: &[Vec<f32>], k: usize) -> KMeans {
+    let mut centroids = vec![rng.choose(data).unwrap().clone()];
+    let mut nearest = data.iter().map(|x| distance_squared(x, &centroids[0])).collect::<Vec<_>>();
+
+    while centroids.len() < k {
+      let total = nearest.iter().sum::<f32>();
+      let next = if total > 0.0 {
+        let mut target = rng.gen::<f32>() * total;
+        nearest.iter().position(|&d| { target -= d; target < 0.0 }).unwrap_or(data.len() - 1)
+      } else {
+        rng.gen_range(0, data.len())
+      };
+      centroids.push(data[next].clone());
+      for (d, x) in nearest.iter_mut().zip(data) {
+        *d = d.min(distance_squared(x, &centroids[centroids.len() - 1]));
+      }
+    }
+
+    KMeans { centroids: centroids }
+  }
+
+  fn lloyd(&mut self, data: &[Vec<f32>], max_iterations: usize) {
+    let mut assignments = self.assign_all(data);
+    for _ in 0..max_iterations {
+      let dim = self.centroids[0].len();
+      let mut sums = vec![vec![0.0; dim]; self.centroids.len()];
+      let mut counts = vec![0; self.centroids.len()];
+      for (x, &c) in data.iter().zip(&assignments) {
+        counts[c] += 1;
+        for (s, v) in sums[c].iter_mut().zip(x) {
+          *s += v;
+        }
+      }
+      // empty clusters keep their centroid
+      for (c, (sum, &count)) in sums.into_iter().zip(&counts).enumerate() {
+        if count > 0 {
+          self.centroids[c] = sum.into_iter().map(|s| s / count as f32).collect();
+        }
+      }
+
+      let next = self.assign_all(data);
+      if next == assignments {
+        break;
+      }
+      assignments = next;
+    }
+  }
+
+  /// Index of the centroid nearest to `x`.
+  pub fn assign(&self, x: &[f32]) -> usize {
+    let distances = self.centroids.iter().map(|c| distance_squared(c, x)).collect::<Vec<_>>();
+    (1..distances.len()).fold(0, |best, it| if distances[it] < distances[best] { it } else { best })
+  }
+
+  pub fn assign_all(&self, data: &[Vec<f32>]) -> Vec<usize> {
+    use rayon::prelude::*;
+
+    data.par_iter().map(|x| self.assign(x)).collect()
+  }
+
+  /// Sum of squared distances between the points and their centroids.
+  pub fn inertia(&self, data: &[Vec<f32>]) -> f32 {
+    use rayon::prelude::*;
+
+    data.par_iter().map(|x| distance_squared(x, &self.centroids[self.assign(x)])).sum()
+  }
+}
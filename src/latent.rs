@@ -0,0 +1,31 @@
+/// Codes along the way from `a` to `b`, both included. Spherical interpolation keeps to the arc
+/// between them, which suits codes whose norm carries little meaning.
+pub fn interpolate(a: &[f32], b: &[f32], steps: usize, spherical: bool) -> Vec<Vec<f32>> {
+  let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+  let cos = a.iter().zip(b).map(|(x, y)| x * y).sum::<f32>() / (norm(a) * norm(b)).max(1e-12);
+  let omega = cos.max(-1.0).min(1.0).acos();
+
+  (0..steps)
+    .map(|it| {
+      let t = if steps > 1 { it as f32 / (steps - 1) as f32 } else { 0.0 };
+      let (wa, wb) = if spherical && omega.sin().abs() > 1e-6 {
+        (((1.0 - t) * omega).sin() / omega.sin(), (t * omega).sin() / omega.sin())
+      } else {
+        (1.0 - t, t)
+      };
+      a.iter().zip(b).map(|(x, y)| wa * x + wb * y).collect()
+    })
+    .collect()
+}
+
+/// Copies of `code` with `unit` swept evenly from `from` to `to`.
+pub fn traverse(code: &[f32], unit: usize, from: f32, to: f32, steps: usize) -> Vec<Vec<f32>> {
+  (0..steps)
+    .map(|it| {
+      let t = if steps > 1 { it as f32 / (steps - 1) as f32 } else { 0.0 };
+      let mut swept = code.to_vec();
+      swept[unit] = from + t * (to - from);
+      swept
+    })
+    .collect()
+}
@@ -0,0 +1,488 @@
+use rand::Rng;
+
+use na::{DMatrix, DVector};
+
+use nn::ActivationFunction;
+use graph::{Graph, GraphCache};
+use batch_norm::{BatchNorm, BatchNormCache};
+use maxout::{Maxout, MaxoutCache};
+use recurrent::{Recurrent, RecurrentCache};
+
+pub trait Layer {
+  type Cache;
+
+  fn input_size(&self) -> usize;
+  fn output_size(&self) -> usize;
+
+  /// Runs the layer over a whole batch. The returned cache holds whatever `backward` needs.
+  fn forward(&self, inputs: &[DVector<f32>], train: bool) -> (Vec<DVector<f32>>, Self::Cache);
+
+  /// Takes the error gradient w.r.t. every output of the batch and returns the gradient w.r.t.
+  /// every input, along with the gradient of each slice in `params`, summed over the batch.
+  fn backward(&self, cache: &Self::Cache, output_deltas: &[DVector<f32>]) -> (Vec<DVector<f32>>, Vec<Vec<f32>>);
+
+  /// Like `backward`, but only returns the gradient w.r.t. the inputs. Layers override it where
+  /// skipping the parameter gradients saves work, e.g. when they are frozen.
+  fn backward_inputs(&self, cache: &Self::Cache, output_deltas: &[DVector<f32>]) -> Vec<DVector<f32>> {
+    self.backward(cache, output_deltas).0
+  }
+
+  fn params(&self) -> Vec<&[f32]>;
+  fn params_mut(&mut self) -> Vec<&mut [f32]>;
+
+  /// Whether the parameter slice at `idx` is subject to weight decay.
+  fn decays(&self, idx: usize) -> bool {
+    idx == 0
+  }
+
+  fn assign_random_weights<R: Rng>(&mut self, rng: &mut R) {
+    use rand::distributions::{Normal, IndependentSample};
+
+    let dist = Normal::new(0.0, 0.1);
+    for param in self.params_mut() {
+      for p in param.iter_mut() {
+        *p = dist.ind_sample(rng) as f32;
+      }
+    }
+  }
+
+  /// Updates state that is not trained by gradient descent, e.g. batch normalisation statistics,
+  /// from the cache of a training-mode `forward`.
+  fn update_statistics(&mut self, _cache: &Self::Cache) {}
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum LayerKind {
+  Dense(Dense),
+  Graph(Graph),
+  Maxout(Maxout),
+  Recurrent(Recurrent),
+  Softmax(Softmax),
+}
+
+pub enum LayerCache {
+  Dense(DenseCache),
+  Graph(GraphCache),
+  Maxout(MaxoutCache),
+  Recurrent(RecurrentCache),
+  Softmax(SoftmaxCache),
+}
+
+impl Layer for LayerKind {
+  type Cache = LayerCache;
+
+  fn input_size(&self) -> usize {
+    match self {
+      &LayerKind::Dense(ref l) => l.input_size(),
+      &LayerKind::Graph(ref l) => l.input_size(),
+      &LayerKind::Maxout(ref l) => l.input_size(),
+      &LayerKind::Recurrent(ref l) => l.input_size(),
+      &LayerKind::Softmax(ref l) => l.input_size(),
+    }
+  }
+
+  fn output_size(&self) -> usize {
+    match self {
+      &LayerKind::Dense(ref l) => l.output_size(),
+      &LayerKind::Graph(ref l) => l.output_size(),
+      &LayerKind::Maxout(ref l) => l.output_size(),
+      &LayerKind::Recurrent(ref l) => l.output_size(),
+      &LayerKind::Softmax(ref l) => l.output_size(),
+    }
+  }
+
+  fn forward(&self, inputs: &[DVector<f32>], train: bool) -> (Vec<DVector<f32>>, LayerCache) {
+    match self {
+      &LayerKind::Dense(ref l) => {
+        let (out, cache) = l.forward(inputs, train);
+        (out, LayerCache::Dense(cache))
+      },
+      &LayerKind::Graph(ref l) => {
+        let (out, cache) = l.forward(inputs, train);
+        (out, LayerCache::Graph(cache))
+      },
+      &LayerKind::Maxout(ref l) => {
+        let (out, cache) = l.forward(inputs, train);
+        (out, LayerCache::Maxout(cache))
+      },
+      &LayerKind::Recurrent(ref l) => {
+        let (out, cache) = l.forward(inputs, train);
+        (out, LayerCache::Recurrent(cache))
+      },
+      &LayerKind::Softmax(ref l) => {
+        let (out, cache) = l.forward(inputs, train);
+        (out, LayerCache::Softmax(cache))
+      },
+    }
+  }
+
+  fn backward(&self, cache: &LayerCache, output_deltas: &[DVector<f32>]) -> (Vec<DVector<f32>>, Vec<Vec<f32>>) {
+    match (self, cache) {
+      (&LayerKind::Dense(ref l), &LayerCache::Dense(ref c)) => l.backward(c, output_deltas),
+      (&LayerKind::Graph(ref l), &LayerCache::Graph(ref c)) => l.backward(c, output_deltas),
+      (&LayerKind::Maxout(ref l), &LayerCache::Maxout(ref c)) => l.backward(c, output_deltas),
+      (&LayerKind::Recurrent(ref l), &LayerCache::Recurrent(ref c)) => l.backward(c, output_deltas),
+      (&LayerKind::Softmax(ref l), &LayerCache::Softmax(ref c)) => l.backward(c, output_deltas),
+      _ => panic!("layer cache does not match the layer"),
+    }
+  }
+
+  fn backward_inputs(&self, cache: &LayerCache, output_deltas: &[DVector<f32>]) -> Vec<DVector<f32>> {
+    match (self, cache) {
+      (&LayerKind::Dense(ref l), &LayerCache::Dense(ref c)) => l.backward_inputs(c, output_deltas),
+      (&LayerKind::Graph(ref l), &LayerCache::Graph(ref c)) => l.backward_inputs(c, output_deltas),
+      (&LayerKind::Maxout(ref l), &LayerCache::Maxout(ref c)) => l.backward_inputs(c, output_deltas),
+      (&LayerKind::Recurrent(ref l), &LayerCache::Recurrent(ref c)) => l.backward_inputs(c, output_deltas),
+      (&LayerKind::Softmax(ref l), &LayerCache::Softmax(ref c)) => l.backward_inputs(c, output_deltas),
+      _ => panic!("layer cache does not match the layer"),
+    }
+  }
+
+  fn params(&self) -> Vec<&[f32]> {
+    match self {
+      &LayerKind::Dense(ref l) => l.params(),
+      &LayerKind::Graph(ref l) => l.params(),
+      &LayerKind::Maxout(ref l) => l.params(),
+      &LayerKind::Recurrent(ref l) => l.params(),
+      &LayerKind::Softmax(ref l) => l.params(),
+    }
+  }
+
+  fn params_mut(&mut self) -> Vec<&mut [f32]> {
+    match self {
+      &mut LayerKind::Dense(ref mut l) => l.params_mut(),
+      &mut LayerKind::Graph(ref mut l) => l.params_mut(),
+      &mut LayerKind::Maxout(ref mut l) => l.params_mut(),
+      &mut LayerKind::Recurrent(ref mut l) => l.params_mut(),
+      &mut LayerKind::Softmax(ref mut l) => l.params_mut(),
+    }
+  }
+
+  fn decays(&self, idx: usize) -> bool {
+    match self {
+      &LayerKind::Dense(ref l) => l.decays(idx),
+      &LayerKind::Graph(ref l) => l.decays(idx),
+      &LayerKind::Maxout(ref l) => l.decays(idx),
+      &LayerKind::Recurrent(ref l) => l.decays(idx),
+      &LayerKind::Softmax(ref l) => l.decays(idx),
+    }
+  }
+
+  fn assign_random_weights<R: Rng>(&mut self, rng: &mut R) {
+    match self {
+      &mut LayerKind::Dense(ref mut l) => l.assign_random_weights(rng),
+      &mut LayerKind::Graph(ref mut l) => l.assign_random_weights(rng),
+      &mut LayerKind::Maxout(ref mut l) => l.assign_random_weights(rng),
+      &mut LayerKind::Recurrent(ref mut l) => l.assign_random_weights(rng),
+      &mut LayerKind::Softmax(ref mut l) => l.assign_random_weights(rng),
+    }
+  }
+
+  fn update_statistics(&mut self, cache: &LayerCache) {
+    match (self, cache) {
+      (&mut LayerKind::Dense(ref mut l), &LayerCache::Dense(ref c)) => l.update_statistics(c),
+      (&mut LayerKind::Graph(ref mut l), &LayerCache::Graph(ref c)) => l.update_statistics(c),
+      (&mut LayerKind::Maxout(ref mut l), &LayerCache::Maxout(ref c)) => l.update_statistics(c),
+      (&mut LayerKind::Recurrent(ref mut l), &LayerCache::Recurrent(ref c)) => l.update_statistics(c),
+      (&mut LayerKind::Softmax(ref mut l), &LayerCache::Softmax(ref c)) => l.update_statistics(c),
+      _ => panic!("layer cache does not match the layer"),
+    }
+  }
+}
+
+/// Fully connected layer followed by an elementwise activation. `weights` is `inputs x outputs`,
+/// so a layer's input is treated as a row vector. With `batch_norm` set, the activation is
+/// applied to the normalised pre-activations.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Dense {
+  pub weights: DMatrix<f32>,
+  pub biases: DVector<f32>,
+  pub activation_fn: ActivationFunction,
+  /// Either a single coefficient shared by the layer, or one per unit.
+  pub activation_coeffs: DVector<f32>,
+  pub learn_coeffs: bool,
+  pub batch_norm: Option<BatchNorm>,
+}
+
+pub struct DenseCache {
+  inputs: Vec<DVector<f32>>,
+  layer_inputs: Vec<DVector<f32>>,
+  batch_norm: Option<BatchNormCache>,
+}
+
+impl Dense {
+  pub fn new(inputs: usize, outputs: usize, activation_fn: ActivationFunction, activation_coeff: f32) -> Dense {
+    Dense {
+      weights: DMatrix::new_zeros(inputs, outputs),
+      biases: DVector::new_zeros(outputs),
+      activation_fn: activation_fn,
+      activation_coeffs: DVector { at: vec![activation_coeff] },
+      learn_coeffs: false,
+      batch_norm: None,
+    }
+  }
+
+  /// Trains the activation coefficients along with the weights, either one for the whole layer
+  /// or one per unit, starting from the current shared value.
+  pub fn with_learned_coeffs(mut self, per_unit: bool) -> Dense {
+    if per_unit {
+      let coeff = self.activation_coeffs[0];
+      self.activation_coeffs = DVector::from_fn(self.output_size(), |_| coeff);
+    }
+    self.learn_coeffs = true;
+    self
+  }
+
+  fn coeff_index(&self, unit: usize) -> usize {
+    if self.activation_coeffs.len() == 1 { 0 } else { unit }
+  }
+
+  /// Gradient w.r.t. the activation inputs, before batch normalisation is accounted for.
+  fn activation_deltas(&self, cache: &DenseCache, output_deltas: &[DVector<f32>]) -> Vec<DVector<f32>> {
+    output_deltas.iter().zip(&cache.layer_inputs)
+      .map(|(d, z)| (0..self.output_size()).map(|u| d[u] * self.activation_fn.derivative(z[u], self.activation_coeffs[self.coeff_index(u)])).collect())
+      .collect()
+  }
+
+  pub fn with_batch_norm(mut self) -> Dense {
+    self.batch_norm = Some(BatchNorm::new(self.output_size()));
+    self
+  }
+}
+
+impl Layer for Dense {
+  type Cache = DenseCache;
+
+  fn input_size(&self) -> usize {
+    self.weights.nrows()
+  }
+
+  fn output_size(&self) -> usize {
+    self.weights.ncols()
+  }
+
+  fn forward(&self, inputs: &[DVector<f32>], train: bool) -> (Vec<DVector<f32>>, DenseCache) {
+    use na::Iterable;
+    use rayon::prelude::*;
+
+    let linear = inputs.par_iter()
+      .map(|input| {
+        debug_assert_eq!(input.len(), self.input_size());
+        let mut net = input.clone();
+        net *= &self.weights;
+        net.iter().zip(self.biases.iter()).map(|(&net, &b)| net + b).collect::<DVector<f32>>()
+      })
+      .collect::<Vec<_>>();
+    let (layer_inputs, batch_norm) = match self.batch_norm {
+      Some(ref bn) => {
+        let (normalized, cache) = bn.forward(&linear, train);
+        (normalized, Some(cache))
+      },
+      None => (linear, None),
+    };
+    let outputs = layer_inputs.iter()
+      .map(|z| z.iter().enumerate().map(|(u, &inp)| self.activation_fn.function(inp, self.activation_coeffs[self.coeff_index(u)])).collect())
+      .collect();
+
+    (outputs, DenseCache { inputs: inputs.to_vec(), layer_inputs: layer_inputs, batch_norm: batch_norm })
+  }
+
+  fn backward(&self, cache: &DenseCache, output_deltas: &[DVector<f32>]) -> (Vec<DVector<f32>>, Vec<Vec<f32>>) {
+    use na::Outer;
+    use rayon::prelude::*;
+
+    let delta = self.activation_deltas(cache, output_deltas);
+    let coeff_update = if self.learn_coeffs {
+      let mut update = vec![0.0; self.activation_coeffs.len()];
+      for (d, z) in output_deltas.iter().zip(&cache.layer_inputs) {
+        for u in 0..self.output_size() {
+          let c = self.coeff_index(u);
+          update[c] += d[u] * self.activation_fn.coeff_derivative(z[u], self.activation_coeffs[c]);
+        }
+      }
+      Some(update)
+    } else {
+      None
+    };
+    let (delta, batch_norm_update) = match (&self.batch_norm, &cache.batch_norm) {
+      (&Some(ref bn), &Some(ref c)) => bn.backward(c, &delta),
+      _ => (delta, Vec::new()),
+    };
+
+    let input_deltas = delta.par_iter().map(|d| &self.weights * d).collect();
+
+    let (weight_update, bias_update) = cache.inputs.par_iter().zip(delta.par_iter())
+      .map(|(input, d)| (input.outer(d), d.clone()))
+      .reduce(|| (DMatrix::new_zeros(self.input_size(), self.output_size()), DVector::new_zeros(self.output_size())),
+        |(mut a_w, mut a_b), (b_w, b_b)| {
+          a_w += b_w;
+          a_b += b_b;
+          (a_w, a_b)
+        });
+
+    let mut update = vec![weight_update.as_vector().to_vec(), bias_update.at];
+    update.extend(batch_norm_update);
+    update.extend(coeff_update);
+    (input_deltas, update)
+  }
+
+  fn backward_inputs(&self, cache: &DenseCache, output_deltas: &[DVector<f32>]) -> Vec<DVector<f32>> {
+    use rayon::prelude::*;
+
+    let delta = self.activation_deltas(cache, output_deltas);
+    let delta = match (&self.batch_norm, &cache.batch_norm) {
+      (&Some(ref bn), &Some(ref c)) => bn.backward(c, &delta).0,
+      _ => delta,
+    };
+
+    delta.par_iter().map(|d| &self.weights * d).collect()
+  }
+
+  fn params(&self) -> Vec<&[f32]> {
+    let mut params = vec![self.weights.as_vector(), &self.biases.at[..]];
+    if let Some(ref bn) = self.batch_norm {
+      params.push(&bn.scale.at[..]);
+      params.push(&bn.shift.at[..]);
+    }
+    if self.learn_coeffs {
+      params.push(&self.activation_coeffs.at[..]);
+    }
+    params
+  }
+
+  fn params_mut(&mut self) -> Vec<&mut [f32]> {
+    let mut params = vec![self.weights.as_mut_vector(), &mut self.biases.at[..]];
+    if let Some(ref mut bn) = self.batch_norm {
+      params.push(&mut bn.scale.at[..]);
+      params.push(&mut bn.shift.at[..]);
+    }
+    if self.learn_coeffs {
+      params.push(&mut self.activation_coeffs.at[..]);
+    }
+    params
+  }
+
+  fn assign_random_weights<R: Rng>(&mut self, rng: &mut R) {
+    use rand::distributions::{Normal, IndependentSample};
+
+    let dist = Normal::new(0.0, 0.1);
+    for w in self.weights.as_mut_vector().iter_mut().chain(self.biases.at.iter_mut()) {
+      *w = dist.ind_sample(rng) as f32;
+    }
+    if let Some(ref mut bn) = self.batch_norm {
+      bn.reset_params();
+    }
+  }
+
+  fn update_statistics(&mut self, cache: &DenseCache) {
+    if let (&mut Some(ref mut bn), &Some(ref c)) = (&mut self.batch_norm, &cache.batch_norm) {
+      bn.update_statistics(c);
+    }
+  }
+}
+
+/// Affine map followed by a softmax over the outputs, meant as the output layer of a classifier
+/// trained with the cross-entropy loss.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Softmax {
+  pub linear: Dense,
+}
+
+pub struct SoftmaxCache {
+  linear: DenseCache,
+  outputs: Vec<DVector<f32>>,
+}
+
+impl Softmax {
+  pub fn new(inputs: usize, outputs: usize) -> Softmax {
+    Softmax { linear: Dense::new(inputs, outputs, ActivationFunction::Identity, 1.0) }
+  }
+
+  fn logit_deltas(&self, cache: &SoftmaxCache, output_deltas: &[DVector<f32>]) -> Vec<DVector<f32>> {
+    use na::{Dot, Iterable};
+
+    output_deltas.iter().zip(&cache.outputs)
+      .map(|(d, p)| {
+        let dp = d.dot(p);
+        d.iter().zip(p.iter()).map(|(&d, &p)| p * (d - dp)).collect()
+      })
+      .collect()
+  }
+}
+
+impl Layer for Softmax {
+  type Cache = SoftmaxCache;
+
+  fn input_size(&self) -> usize {
+    self.linear.input_size()
+  }
+
+  fn output_size(&self) -> usize {
+    self.linear.output_size()
+  }
+
+  fn forward(&self, inputs: &[DVector<f32>], train: bool) -> (Vec<DVector<f32>>, SoftmaxCache) {
+    use na::Iterable;
+
+    let (logits, linear) = self.linear.forward(inputs, train);
+    let outputs = logits.into_iter()
+      .map(|z| {
+        let max = z.iter().cloned().fold(::std::f32::NEG_INFINITY, f32::max);
+        let exp = z.iter().map(|&z| (z - max).exp()).collect::<DVector<f32>>();
+        let sum = exp.iter().sum::<f32>();
+        exp.iter().map(|&e| e / sum).collect()
+      })
+      .collect::<Vec<DVector<f32>>>();
+
+    (outputs.clone(), SoftmaxCache { linear: linear, outputs: outputs })
+  }
+
+  fn backward(&self, cache: &SoftmaxCache, output_deltas: &[DVector<f32>]) -> (Vec<DVector<f32>>, Vec<Vec<f32>>) {
+    self.linear.backward(&cache.linear, &self.logit_deltas(cache, output_deltas))
+  }
+
+  fn backward_inputs(&self, cache: &SoftmaxCache, output_deltas: &[DVector<f32>]) -> Vec<DVector<f32>> {
+    self.linear.backward_inputs(&cache.linear, &self.logit_deltas(cache, output_deltas))
+  }
+
+  fn params(&self) -> Vec<&[f32]> {
+    self.linear.params()
+  }
+
+  fn params_mut(&mut self) -> Vec<&mut [f32]> {
+    self.linear.params_mut()
+  }
+
+  fn assign_random_weights<R: Rng>(&mut self, rng: &mut R) {
+    self.linear.assign_random_weights(rng)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use gradient_check::{check, layers};
+
+  #[test]
+  fn dense_gradients() {
+    for &(f, coeff) in &[("sigmoid", 2.0), ("tanh", 1.0), ("id", 0.7)] {
+      let json = format!(r#"{{"layers": [5, 4], "activation_coeffs": [{}], "activation_fn": "{}"}}"#, coeff, f);
+      check(layers(&json).remove(0), 3);
+    }
+  }
+
+  #[test]
+  fn learned_coeff_gradients() {
+    for f in &["sigmoid", "tanh", "id"] {
+      for mode in &["layer", "unit"] {
+        let json = format!(r#"{{"layers": [5, 4], "activation_coeffs": [1.5], "activation_fn": "{}", "learned_coeffs": "{}", "batch_norm": [true]}}"#, f, mode);
+        check(layers(&json).remove(0), 3);
+      }
+    }
+  }
+
+  #[test]
+  fn softmax_gradients() {
+    let json = r#"{"layers": [5, 4], "activation_coeffs": [1], "activation_fn": "sigmoid", "layer_types": ["softmax"]}"#;
+    check(layers(json).remove(0), 3);
+  }
+}
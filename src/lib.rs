@@ -12,7 +12,41 @@ extern crate rayon;
 extern crate ctrlc;
 
 pub mod nn;
+pub mod layer;
+pub mod graph;
+pub mod batch_norm;
+pub mod maxout;
+pub mod recurrent;
+pub mod som;
+pub mod metrics;
+pub mod surgery;
+pub mod probe;
+pub mod kmeans;
+pub mod pca;
+pub mod tsne;
+pub mod latent;
+pub mod visualize;
+pub mod adversarial;
+pub mod inpaint;
 pub mod mnist;
 pub mod program_args;
+#[cfg(test)]
+mod gradient_check;
 
-pub use nn::*;
\ No newline at end of file
+pub use nn::*;
+pub use layer::*;
+pub use graph::*;
+pub use batch_norm::*;
+pub use maxout::*;
+pub use recurrent::*;
+pub use som::*;
+pub use metrics::*;
+pub use surgery::*;
+pub use probe::*;
+pub use kmeans::*;
+pub use pca::*;
+pub use tsne::*;
+pub use latent::*;
+pub use visualize::*;
+pub use adversarial::*;
+pub use inpaint::*;
\ No newline at end of file
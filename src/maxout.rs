@@ -0,0 +1,122 @@
+use na::{DMatrix, DVector};
+
+use layer::Layer;
+
+/// Every unit outputs the maximum of `pieces` affine functions of the input. Piece `p` of unit
+/// `u` is column `u * pieces + p` of `weights`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Maxout {
+  pub weights: DMatrix<f32>,
+  pub biases: DVector<f32>,
+  pub pieces: usize,
+}
+
+pub struct MaxoutCache {
+  inputs: Vec<DVector<f32>>,
+  winners: Vec<Vec<usize>>,
+}
+
+impl Maxout {
+  pub fn new(inputs: usize, outputs: usize, pieces: usize) -> Maxout {
+    Maxout {
+      weights: DMatrix::new_zeros(inputs, outputs * pieces),
+      biases: DVector::new_zeros(outputs * pieces),
+      pieces: pieces,
+    }
+  }
+
+  /// Spreads the gradient of every unit onto its winning piece only.
+  fn piece_deltas(&self, cache: &MaxoutCache, output_deltas: &[DVector<f32>]) -> Vec<DVector<f32>> {
+    output_deltas.iter().zip(&cache.winners)
+      .map(|(d, winners)| {
+        let mut delta = DVector::new_zeros(self.weights.ncols());
+        for (u, &w) in winners.iter().enumerate() {
+          delta[w] = d[u];
+        }
+        delta
+      })
+      .collect()
+  }
+}
+
+impl Layer for Maxout {
+  type Cache = MaxoutCache;
+
+  fn input_size(&self) -> usize {
+    self.weights.nrows()
+  }
+
+  fn output_size(&self) -> usize {
+    self.weights.ncols() / self.pieces
+  }
+
+  fn forward(&self, inputs: &[DVector<f32>], _train: bool) -> (Vec<DVector<f32>>, MaxoutCache) {
+    use na::Iterable;
+    use rayon::prelude::*;
+
+    let (outputs, winners): (Vec<DVector<f32>>, Vec<Vec<usize>>) = inputs.par_iter()
+      .map(|input| {
+        let mut net = input.clone();
+        net *= &self.weights;
+        let net = net.iter().zip(self.biases.iter()).map(|(&net, &b)| net + b).collect::<Vec<_>>();
+
+        let winners = net.chunks(self.pieces).enumerate()
+          .map(|(u, pieces)| {
+            let best = (1..pieces.len()).fold(0, |best, p| if pieces[p] > pieces[best] { p } else { best });
+            u * self.pieces + best
+          })
+          .collect::<Vec<_>>();
+        (winners.iter().map(|&w| net[w]).collect(), winners)
+      })
+      .collect::<Vec<_>>()
+      .into_iter()
+      .unzip();
+
+    (outputs, MaxoutCache { inputs: inputs.to_vec(), winners: winners })
+  }
+
+  fn backward(&self, cache: &MaxoutCache, output_deltas: &[DVector<f32>]) -> (Vec<DVector<f32>>, Vec<Vec<f32>>) {
+    use na::Outer;
+    use rayon::prelude::*;
+
+    let delta = self.piece_deltas(cache, output_deltas);
+
+    let input_deltas = delta.par_iter().map(|d| &self.weights * d).collect();
+
+    let (weight_update, bias_update) = cache.inputs.par_iter().zip(delta.par_iter())
+      .map(|(input, d)| (input.outer(d), d.clone()))
+      .reduce(|| (DMatrix::new_zeros(self.weights.nrows(), self.weights.ncols()), DVector::new_zeros(self.weights.ncols())),
+        |(mut a_w, mut a_b), (b_w, b_b)| {
+          a_w += b_w;
+          a_b += b_b;
+          (a_w, a_b)
+        });
+
+    (input_deltas, vec![weight_update.as_vector().to_vec(), bias_update.at])
+  }
+
+  fn backward_inputs(&self, cache: &MaxoutCache, output_deltas: &[DVector<f32>]) -> Vec<DVector<f32>> {
+    use rayon::prelude::*;
+
+    self.piece_deltas(cache, output_deltas).par_iter().map(|d| &self.weights * d).collect()
+  }
+
+  fn params(&self) -> Vec<&[f32]> {
+    vec![self.weights.as_vector(), &self.biases.at[..]]
+  }
+
+  fn params_mut(&mut self) -> Vec<&mut [f32]> {
+    vec![self.weights.as_mut_vector(), &mut self.biases.at[..]]
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use gradient_check::{check, layers};
+
+  #[test]
+  fn maxout_gradients() {
+    let json = r#"{"layers": [5, 4, 3], "activation_coeffs": [1, 1], "activation_fn": "sigmoid", "layer_types": ["maxout"], "maxout_pieces": 3}"#;
+    check(layers(json).remove(0), 3);
+  }
+}
@@ -0,0 +1,237 @@
+use nn::argmax;
+
+/// `counts[actual][predicted]` is the number of examples of class `actual` predicted as `predicted`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ConfusionMatrix {
+  pub counts: Vec<Vec<usize>>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct ClassMetrics {
+  pub precision: f32,
+  pub recall: f32,
+  pub f1: f32,
+  pub support: usize,
+}
+
+impl ConfusionMatrix {
+  pub fn new(classes: usize) -> ConfusionMatrix {
+    ConfusionMatrix {
+      counts: vec![vec![0; classes]; classes],
+    }
+  }
+
+  pub fn add(&mut self, actual: usize, predicted: usize) {
+    self.counts[actual][predicted] += 1;
+  }
+
+  pub fn classes(&self) -> usize {
+    self.counts.len()
+  }
+
+  pub fn total(&self) -> usize {
+    self.counts.iter().map(|row| row.iter().sum::<usize>()).sum()
+  }
+
+  pub fn correct(&self) -> usize {
+    (0..self.classes()).map(|c| self.counts[c][c]).sum()
+  }
+
+  pub fn accuracy(&self) -> f32 {
+    self.correct() as f32 / self.total() as f32
+  }
+
+  /// Precision, recall and F1 of a single class; undefined ratios are reported as 0.
+  pub fn class_metrics(&self, class: usize) -> ClassMetrics {
+    fn ratio(a: usize, b: usize) -> f32 {
+      if b == 0 { 0.0 } else { a as f32 / b as f32 }
+    }
+
+    let hits = self.counts[class][class];
+    let predicted = self.counts.iter().map(|row| row[class]).sum::<usize>();
+    let support = self.counts[class].iter().sum::<usize>();
+    let precision = ratio(hits, predicted);
+    let recall = ratio(hits, support);
+
+    ClassMetrics {
+      precision: precision,
+      recall: recall,
+      f1: if precision + recall > 0.0 { 2.0 * precision * recall / (precision + recall) } else { 0.0 },
+      support: support,
+    }
+  }
+}
+
+/// Value below which fraction `p` of the ascending `sorted` values lie (nearest rank).
+pub fn percentile(sorted: &[f32], p: f32) -> f32 {
+  let rank = (p * sorted.len() as f32).ceil() as usize;
+  sorted[rank.max(1).min(sorted.len()) - 1]
+}
+
+/// Mean squared difference between an example and its reconstruction.
+pub fn reconstruction_error(example: &[f32], reconstruction: &[f32]) -> f32 {
+  example.iter().zip(reconstruction).map(|(x, y)| (x - y) * (x - y)).sum::<f32>() / example.len() as f32
+}
+
+/// Score above which fraction `false_positive_rate` of the `clean` scores lie at most.
+pub fn calibrate_threshold(clean: &[f32], false_positive_rate: f32) -> f32 {
+  let mut sorted = clean.to_vec();
+  sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+  percentile(&sorted, 1.0 - false_positive_rate)
+}
+
+/// Area under the ROC curve of `scores` separating the examples marked in `anomalous` from the
+/// rest: the probability that an anomaly scores higher than a normal example, ties counting half.
+/// `None` if the examples are all anomalies or all normal, as the curve is then undefined.
+pub fn roc_auc(scores: &[f32], anomalous: &[bool]) -> Option<f32> {
+  let positives = anomalous.iter().filter(|&&a| a).count() as f64;
+  let negatives = anomalous.len() as f64 - positives;
+  if positives == 0.0 || negatives == 0.0 {
+    return None;
+  }
+
+  let mut order = (0..scores.len()).collect::<Vec<_>>();
+  order.sort_by(|&a, &b| scores[a].partial_cmp(&scores[b]).unwrap());
+
+  // average 1-based ranks, shared within runs of tied scores
+  let mut ranks = vec![0.0; scores.len()];
+  let mut start = 0;
+  while start < order.len() {
+    let end = (start..order.len()).find(|&it| scores[order[it]] != scores[order[start]]).unwrap_or(order.len());
+    for &it in &order[start..end] {
+      ranks[it] = (start + end + 1) as f64 / 2.0;
+    }
+    start = end;
+  }
+
+  let rank_sum = ranks.iter().zip(anomalous).filter(|&(_, &a)| a).map(|(r, _)| r).sum::<f64>();
+  Some(((rank_sum - positives * (positives + 1.0) / 2.0) / (positives * negatives)) as f32)
+}
+
+/// Mean squared difference over the pixels where `known` is false, e.g. those an inpainting
+/// filled in.
+pub fn masked_reconstruction_error(example: &[f32], reconstruction: &[f32], known: &[bool]) -> f32 {
+  let (sum, count) = example.iter().zip(reconstruction).zip(known)
+    .filter(|&(_, &k)| !k)
+    .fold((0.0, 0), |(sum, count), ((x, y), _)| (sum + (x - y) * (x - y), count + 1));
+  if count == 0 { 0.0 } else { sum / count as f32 }
+}
+
+/// `counts[cluster][class]` of the points assigned to every cluster.
+fn contingency(assignments: &[usize], labels: &[usize]) -> Vec<Vec<usize>> {
+  let clusters = assignments.iter().cloned().max().map(|m| m + 1).unwrap_or(0);
+  let classes = labels.iter().cloned().max().map(|m| m + 1).unwrap_or(0);
+  let mut counts = vec![vec![0; classes]; clusters];
+  for (&a, &l) in assignments.iter().zip(labels) {
+    counts[a][l] += 1;
+  }
+  counts
+}
+
+/// Fraction of points whose label is the most common one in their cluster.
+pub fn purity(assignments: &[usize], labels: &[usize]) -> f32 {
+  let majority = contingency(assignments, labels).iter()
+    .map(|row| row.iter().cloned().max().unwrap_or(0))
+    .sum::<usize>();
+  majority as f32 / assignments.len() as f32
+}
+
+/// Mutual information between clusters and labels, divided by the mean of their entropies.
+pub fn normalized_mutual_information(assignments: &[usize], labels: &[usize]) -> f32 {
+  fn entropy(counts: &[usize], n: f64) -> f64 {
+    counts.iter().filter(|&&c| c > 0).map(|&c| { let p = c as f64 / n; -p * p.ln() }).sum()
+  }
+
+  let counts = contingency(assignments, labels);
+  let n = assignments.len() as f64;
+  let cluster_sizes = counts.iter().map(|row| row.iter().sum()).collect::<Vec<usize>>();
+  let class_sizes = (0..counts.first().map(|row| row.len()).unwrap_or(0))
+    .map(|l| counts.iter().map(|row| row[l]).sum())
+    .collect::<Vec<usize>>();
+
+  let mut mutual_information = 0.0;
+  for (a, row) in counts.iter().enumerate() {
+    for (l, &c) in row.iter().enumerate().filter(|&(_, &c)| c > 0) {
+      mutual_information += c as f64 / n * (c as f64 * n / (cluster_sizes[a] as f64 * class_sizes[l] as f64)).ln();
+    }
+  }
+  let mean_entropy = (entropy(&cluster_sizes, n) + entropy(&class_sizes, n)) / 2.0;
+  if mean_entropy > 0.0 { (mutual_information / mean_entropy) as f32 } else { 1.0 }
+}
+
+/// Whether `label` is among the `k` largest outputs.
+pub fn in_top_k(output: &[f32], label: usize, k: usize) -> bool {
+  output.iter().filter(|&&x| x > output[label]).count() < k
+}
+
+/// Evaluation summary of a classifier, as written by `test --json`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ClassificationReport {
+  pub examples: usize,
+  pub accuracy: f32,
+  pub top_k: usize,
+  pub top_k_accuracy: f32,
+  pub confusion: ConfusionMatrix,
+  pub classes: Vec<ClassMetrics>,
+}
+
+impl ClassificationReport {
+  pub fn new(outputs: &[Vec<f32>], labels: &[usize], classes: usize, top_k: usize) -> ClassificationReport {
+    let mut confusion = ConfusionMatrix::new(classes);
+    for (output, &label) in outputs.iter().zip(labels) {
+      confusion.add(label, argmax(output));
+    }
+    let top_k_hits = outputs.iter().zip(labels).filter(|&(output, &label)| in_top_k(output, label, top_k)).count();
+
+    ClassificationReport {
+      examples: outputs.len(),
+      accuracy: confusion.accuracy(),
+      top_k: top_k,
+      top_k_accuracy: top_k_hits as f32 / outputs.len() as f32,
+      classes: (0..classes).map(|c| confusion.class_metrics(c)).collect(),
+      confusion: confusion,
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn percentile_uses_the_nearest_rank() {
+    let sorted = (1..11).map(|x| x as f32).collect::<Vec<_>>();
+    assert_eq!(percentile(&sorted, 0.25), 3.0);
+    assert_eq!(percentile(&sorted, 0.5), 5.0);
+    assert_eq!(percentile(&sorted, 0.0), 1.0);
+    assert_eq!(percentile(&sorted, 1.0), 10.0);
+  }
+
+  #[test]
+  fn clustering_scores() {
+    // clusters matching the classes up to their numbering
+    assert_eq!(purity(&[0, 0, 1, 1], &[1, 1, 0, 0]), 1.0);
+    assert!((normalized_mutual_information(&[0, 0, 1, 1], &[1, 1, 0, 0]) - 1.0).abs() < 1e-6);
+    // clusters independent of the classes
+    assert_eq!(purity(&[0, 0, 1, 1], &[0, 1, 0, 1]), 0.5);
+    assert!(normalized_mutual_information(&[0, 0, 1, 1], &[0, 1, 0, 1]).abs() < 1e-6);
+    // MI of 0.5 ln(4/3) + 0.25 ln(2/3) + 0.25 ln 2 over the mean of the entropies
+    assert_eq!(purity(&[0, 0, 0, 1], &[0, 0, 1, 1]), 0.75);
+    assert!((normalized_mutual_information(&[0, 0, 0, 1], &[0, 0, 1, 1]) - 0.343711).abs() < 1e-5);
+  }
+
+  #[test]
+  fn roc_auc_counts_ties_half() {
+    assert_eq!(roc_auc(&[0.1, 0.2, 0.3, 0.4], &[false, false, true, true]), Some(1.0));
+    assert_eq!(roc_auc(&[0.4, 0.3, 0.2, 0.1], &[false, false, true, true]), Some(0.0));
+    assert_eq!(roc_auc(&[0.5, 0.5, 0.5, 0.5], &[false, false, true, true]), Some(0.5));
+    // anomalies at 0.3 and 0.4 against normal examples at 0.1, 0.3 and 0.35: 4.5 of 6 pairs
+    assert!((roc_auc(&[0.1, 0.3, 0.35, 0.4, 0.3], &[false, true, false, true, false]).unwrap() - 4.5 / 6.0).abs() < 1e-6);
+  }
+
+  #[test]
+  fn roc_auc_needs_both_classes() {
+    assert_eq!(roc_auc(&[0.1, 0.2], &[true, true]), None);
+    assert_eq!(roc_auc(&[0.1, 0.2], &[false, false]), None);
+  }
+}
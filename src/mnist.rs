@@ -2,8 +2,20 @@ use ::std::{io, fs};
 use ::bo;
 use bo::ReadBytesExt;
 use ::std::io::Read;
+use ::img;
 
 pub fn load_idx_images(path: &str) -> io::Result<Vec<Vec<f32>>> {
+  read_idx_images(path).map(|(_, _, images)| images)
+}
+
+/// Loads images as sequences of their rows, for recurrent networks.
+pub fn load_idx_image_rows(path: &str) -> io::Result<Vec<Vec<Vec<f32>>>> {
+  let (_, number_of_cols, images) = read_idx_images(path)?;
+
+  Ok(images.into_iter().map(|img| img.chunks(number_of_cols).map(|row| row.to_vec()).collect()).collect())
+}
+
+fn read_idx_images(path: &str) -> io::Result<(usize, usize, Vec<Vec<f32>>)> {
   let mut bytes = Vec::new();
   {
     let mut file = fs::File::open(path)?;
@@ -27,7 +39,7 @@ pub fn load_idx_images(path: &str) -> io::Result<Vec<Vec<f32>>> {
     result.push(item);
   }
 
-  Ok(result)
+  Ok((number_of_rows, number_of_cols, result))
 }
 
 pub fn load_idx_images_halved(path: &str) -> io::Result<Vec<Vec<f32>>> {
@@ -53,6 +65,44 @@ pub fn load_idx_images_halved(path: &str) -> io::Result<Vec<Vec<f32>>> {
   Ok(result)
 }
 
+/// Loads any image file the `image` crate reads (PNG, JPEG, PGM, ...) and prepares it the way
+/// MNIST digits were: grayscale, a light digit on black (images with a light border are
+/// inverted), its bounding box scaled to fit 20/28 of a `side` x `side` frame and centred in it.
+pub fn load_image_file(path: &str, side: usize) -> img::ImageResult<Vec<f32>> {
+  let mut gray = img::open(path)?.to_luma();
+  let (width, height) = gray.dimensions();
+
+  let border = gray.enumerate_pixels()
+    .filter(|&(x, y, _)| x == 0 || y == 0 || x == width - 1 || y == height - 1)
+    .map(|(_, _, p)| p.data[0] as f32)
+    .collect::<Vec<_>>();
+  if border.iter().sum::<f32>() / border.len() as f32 > 127.0 {
+    img::imageops::invert(&mut gray);
+  }
+
+  let ink = gray.enumerate_pixels().filter(|&(_, _, p)| p.data[0] > 25).map(|(x, y, _)| (x, y)).collect::<Vec<_>>();
+  let (left, top, right, bottom) = ink.iter().fold((width, height, 0, 0), |(l, t, r, b), &(x, y)| (l.min(x), t.min(y), r.max(x), b.max(y)));
+  let (left, top, right, bottom) = if ink.is_empty() { (0, 0, width - 1, height - 1) } else { (left, top, right, bottom) };
+  let digit = img::ImageBuffer::from_fn(right - left + 1, bottom - top + 1, |x, y| *gray.get_pixel(left + x, top + y));
+
+  let inner = ((side * 20 + 14) / 28).max(1) as f32;
+  let scale = inner / digit.width().max(digit.height()) as f32;
+  let (scaled_width, scaled_height) = (((digit.width() as f32 * scale).round() as u32).max(1), ((digit.height() as f32 * scale).round() as u32).max(1));
+  let scaled = img::imageops::resize(&digit, scaled_width, scaled_height, img::FilterType::Triangle);
+
+  let (offset_x, offset_y) = ((side as u32 - scaled_width) / 2, (side as u32 - scaled_height) / 2);
+  let mut result = vec![0.0; side * side];
+  for (x, y, p) in scaled.enumerate_pixels() {
+    result[((offset_y + y) as usize) * side + (offset_x + x) as usize] = p.data[0] as f32 / 255.0;
+  }
+  Ok(result)
+}
+
+/// Target vector with 1.0 at `label` and zeros elsewhere.
+pub fn one_hot(label: usize, classes: usize) -> Vec<f32> {
+  (0..classes).map(|it| if it == label { 1.0 } else { 0.0 }).collect()
+}
+
 pub fn load_idx_labels(path: &str) -> io::Result<Vec<usize>> {
   let mut bytes = Vec::new();
   {
@@ -2,11 +2,28 @@
 
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use std::{error, fmt};
 
-use na::{DMatrix, DVector, Norm, IterableMut};
+use bc;
+use na::{DMatrix, DVector, Norm};
 
+use layer::{Layer, LayerKind, LayerCache, Dense, Softmax};
+use graph::Graph;
+use maxout::Maxout;
+use recurrent::{Recurrent, Cell};
+use adversarial::AdversarialTraining;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Sequential {
+  pub layers: Vec<LayerKind>,
+}
+
+pub type Network = Sequential;
+
+/// Model layout from before layers were split out of `Network`, kept so that old model files
+/// still load. Index 0 of `weights` and `activation_coeffs` is a placeholder for the input layer.
 #[derive(Serialize, Deserialize, Clone, Debug)]
-pub struct Network {
+pub struct LegacyNetwork {
   pub layer_sizes: Vec<usize>,
   pub activation_coeffs: Vec<f32>,
   pub weights: Vec<DMatrix<f32>>,
@@ -19,6 +36,187 @@ pub struct NetworkDefn {
   pub layers: Vec<usize>,
   pub activation_coeffs: Vec<f32>,
   pub activation_fn: String,
+  /// Whether each layer after the input normalises its pre-activations over the batch, which
+  /// only dense layers support. Missing entries default to `false`.
+  #[serde(default)]
+  pub batch_norm: Vec<bool>,
+  /// `layer` or `unit` to learn the activation coefficients, shared by each layer or separately
+  /// for every unit; `activation_coeffs` then only gives their initial values.
+  #[serde(default)]
+  pub learned_coeffs: Option<String>,
+  /// Type of each layer after the input: `dense`, `maxout`, `rnn`, `gru` or `softmax`. Missing
+  /// entries default to `dense`.
+  #[serde(default)]
+  pub layer_types: Vec<String>,
+  /// Number of linear pieces every maxout unit takes the maximum over.
+  #[serde(default = "NetworkDefn::default_maxout_pieces")]
+  pub maxout_pieces: usize,
+  /// Number of steps recurrent layers split their input into, e.g. 28 to read an image by rows.
+  #[serde(default = "NetworkDefn::default_sequence_steps")]
+  pub sequence_steps: usize,
+  /// Nodes of a layer graph placed after `layers`, whose last size is the graph's input.
+  #[serde(default)]
+  pub nodes: Vec<NodeDefn>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct NodeDefn {
+  pub name: String,
+  /// Names of the nodes feeding this one; `input` is the input of the graph.
+  pub inputs: Vec<String>,
+  #[serde(default = "NodeDefn::default_merge")]
+  pub merge: String,
+  #[serde(default = "NodeDefn::default_layer_type")]
+  pub layer_type: String,
+  /// Number of units of the node's dense layer, or `None` to only merge the inputs.
+  #[serde(default)]
+  pub size: Option<usize>,
+  #[serde(default = "NodeDefn::default_activation_coeff")]
+  pub activation_coeff: f32,
+  #[serde(default)]
+  pub batch_norm: bool,
+}
+
+impl NetworkDefn {
+  fn default_maxout_pieces() -> usize {
+    2
+  }
+
+  fn default_sequence_steps() -> usize {
+    1
+  }
+
+  /// Builds a layer of the given type. Only dense layers support batch norm; other settings that
+  /// do not apply to the type are ignored.
+  pub fn layer(&self, layer_type: &str, inputs: usize, outputs: usize, activation_coeff: f32, batch_norm: bool) -> Result<LayerKind, DefnError> {
+    if batch_norm && layer_type != "dense" {
+      return Err(DefnError::BatchNorm(layer_type.to_owned()));
+    }
+    match layer_type {
+      "dense" => Ok(LayerKind::Dense(self.dense(inputs, outputs, activation_coeff, batch_norm)?)),
+      "maxout" => {
+        if self.maxout_pieces == 0 {
+          return Err(DefnError::Zero("maxout_pieces"));
+        }
+        Ok(LayerKind::Maxout(Maxout::new(inputs, outputs, self.maxout_pieces)))
+      },
+      "rnn" | "gru" => {
+        if self.sequence_steps == 0 {
+          return Err(DefnError::Zero("sequence_steps"));
+        }
+        if inputs % self.sequence_steps != 0 {
+          return Err(DefnError::SequenceSteps { inputs: inputs, steps: self.sequence_steps });
+        }
+        let activation_fn = ActivationFunction::from_name(&self.activation_fn)
+          .ok_or_else(|| DefnError::UnknownActivation(self.activation_fn.clone()))?;
+        let cell = if layer_type == "rnn" { Cell::Elman } else { Cell::Gru };
+        Ok(LayerKind::Recurrent(Recurrent::new(cell, inputs, outputs, self.sequence_steps, activation_fn, activation_coeff)))
+      },
+      "softmax" => Ok(LayerKind::Softmax(Softmax::new(inputs, outputs))),
+      _ => Err(DefnError::UnknownLayerType(layer_type.to_owned())),
+    }
+  }
+
+  /// Builds a dense layer with the activation settings shared by the whole network.
+  pub fn dense(&self, inputs: usize, outputs: usize, activation_coeff: f32, batch_norm: bool) -> Result<Dense, DefnError> {
+    let activation_fn = ActivationFunction::from_name(&self.activation_fn)
+      .ok_or_else(|| DefnError::UnknownActivation(self.activation_fn.clone()))?;
+    let dense = Dense::new(inputs, outputs, activation_fn, activation_coeff);
+    let dense = if batch_norm { dense.with_batch_norm() } else { dense };
+
+    match self.learned_coeffs.as_ref().map(|s| s.as_str()) {
+      None => Ok(dense),
+      Some("layer") => Ok(dense.with_learned_coeffs(false)),
+      Some("unit") => Ok(dense.with_learned_coeffs(true)),
+      Some(other) => Err(DefnError::UnknownCoeffLearning(other.to_owned())),
+    }
+  }
+}
+
+impl NodeDefn {
+  fn default_merge() -> String {
+    "sum".to_owned()
+  }
+
+  fn default_layer_type() -> String {
+    "dense".to_owned()
+  }
+
+  fn default_activation_coeff() -> f32 {
+    1.0
+  }
+}
+
+#[derive(Debug)]
+pub enum DefnError {
+  UnknownActivation(String),
+  UnknownCoeffLearning(String),
+  UnknownLayerType(String),
+  SequenceSteps { inputs: usize, steps: usize },
+  EntryCount { field: &'static str, expected: usize, found: usize },
+  Zero(&'static str),
+  NoLayers,
+  BatchNorm(String),
+  UnknownMerge { node: String, merge: String },
+  UnknownNode { node: String, input: String },
+  DuplicateNode(String),
+  NoInputs(String),
+  SizeMismatch { node: String, expected: usize, found: usize },
+  Cycle(Vec<String>),
+}
+
+impl fmt::Display for DefnError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      &DefnError::UnknownActivation(ref name) => write!(f, "unrecognized activation function: {}", name),
+      &DefnError::UnknownLayerType(ref name) => write!(f, "unrecognized layer type: {}", name),
+      &DefnError::SequenceSteps { inputs, steps } => write!(f, "recurrent layer input of size {} cannot be split into {} steps", inputs, steps),
+      &DefnError::EntryCount { field, expected, found } => write!(f, "`{}` has {} entries but the network has {} layers after the input", field, found, expected),
+      &DefnError::Zero(field) => write!(f, "`{}` must be at least 1", field),
+      &DefnError::BatchNorm(ref layer_type) => write!(f, "batch norm is only supported on dense layers, not {}", layer_type),
+      &DefnError::NoLayers => write!(f, "the network has no layers; `layers` needs the input size followed by more sizes or graph `nodes`"),
+      &DefnError::UnknownCoeffLearning(ref name) => write!(f, "unrecognized coefficient learning mode: {} (expected `layer` or `unit`)", name),
+      &DefnError::UnknownMerge { ref node, ref merge } => write!(f, "node `{}` has unrecognized merge `{}`", node, merge),
+      &DefnError::UnknownNode { ref node, ref input } => write!(f, "node `{}` takes input from unknown node `{}`", node, input),
+      &DefnError::DuplicateNode(ref name) => write!(f, "duplicate or reserved node name `{}`", name),
+      &DefnError::NoInputs(ref name) => write!(f, "node `{}` has no inputs", name),
+      &DefnError::SizeMismatch { ref node, expected, found } => write!(f, "node `{}` sums inputs of different sizes ({} and {})", node, expected, found),
+      &DefnError::Cycle(ref nodes) => write!(f, "network graph has a cycle through nodes: {}", nodes.join(", ")),
+    }
+  }
+}
+
+impl error::Error for DefnError {
+  fn description(&self) -> &str {
+    "invalid network definition"
+  }
+}
+
+/// Data that does not fit where it is fed into a network: a code passed to `eval_from_layer`, or
+/// a training sequence whose steps do not match the recurrent input layer.
+#[derive(Debug)]
+pub enum ShapeError {
+  NoSuchLayer { layer: usize, layers: usize },
+  CodeSize { layer: usize, expected: usize, found: usize },
+  StepSize { expected: usize, found: usize },
+  InputSize { expected: usize, found: usize },
+}
+
+impl fmt::Display for ShapeError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      &ShapeError::NoSuchLayer { layer, layers } => write!(f, "layer {} is not one of the {} layers of the network", layer, layers),
+      &ShapeError::CodeSize { layer, expected, found } => write!(f, "layer {} has {} units but the code has {} values", layer, expected, found),
+      &ShapeError::StepSize { expected, found } => write!(f, "the network reads {} inputs per step but a sequence has a step of {}", expected, found),
+      &ShapeError::InputSize { expected, found } => write!(f, "the network has {} inputs but a sequence has {} in all", expected, found),
+    }
+  }
+}
+
+impl error::Error for ShapeError {
+  fn description(&self) -> &str {
+    "data does not fit the network"
+  }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Copy)]
@@ -29,6 +227,15 @@ pub enum ActivationFunction {
 }
 
 impl ActivationFunction {
+  pub fn from_name(name: &str) -> Option<ActivationFunction> {
+    match name {
+      "sigmoid" => Some(ActivationFunction::Sigmoid),
+      "tanh" => Some(ActivationFunction::Tanh),
+      "id" => Some(ActivationFunction::Identity),
+      _ => None,
+    }
+  }
+
   pub fn function(&self, x: f32, coeff: f32) -> f32 {
     match self {
       &ActivationFunction::Sigmoid => 1.0 / (1.0 + (-x * coeff).exp()),
@@ -40,13 +247,54 @@ impl ActivationFunction {
   pub fn derivative(&self, x: f32, coeff: f32) -> f32 {
     match self {
       &ActivationFunction::Sigmoid => coeff * self.function(x, coeff) * (1.0 - self.function(x, coeff)),
-      &ActivationFunction::Tanh => coeff / (x * coeff).cosh(),
+      &ActivationFunction::Tanh => coeff * (1.0 - (x * coeff).tanh().powi(2)),
       &ActivationFunction::Identity => coeff,
     }
   }
+
+  /// Derivative w.r.t. the coefficient rather than the input.
+  pub fn coeff_derivative(&self, x: f32, coeff: f32) -> f32 {
+    match self {
+      &ActivationFunction::Sigmoid => x * self.function(x, coeff) * (1.0 - self.function(x, coeff)),
+      &ActivationFunction::Tanh => x * (1.0 - (x * coeff).tanh().powi(2)),
+      &ActivationFunction::Identity => x,
+    }
+  }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Loss {
+  SquaredError,
+  /// Meant for `softmax` output layers and one-hot targets.
+  CrossEntropy,
+}
+
+impl Default for Loss {
+  fn default() -> Loss {
+    Loss::SquaredError
+  }
 }
 
-#[derive(Serialize, Deserialize, Clone, Copy)]
+impl Loss {
+  /// Returns the error of a single example and its gradient w.r.t. the network output.
+  pub fn error(&self, output: &DVector<f32>, target: &DVector<f32>) -> (f32, DVector<f32>) {
+    use na::Iterable;
+
+    match self {
+      &Loss::SquaredError => {
+        let diff = output.clone() - target.clone();
+        (diff.norm_squared() / diff.len() as f32, diff)
+      },
+      &Loss::CrossEntropy => {
+        let clamped = output.iter().map(|&p| p.max(1e-7)).collect::<Vec<_>>();
+        let error = -target.iter().zip(&clamped).map(|(&t, &p)| t * p.ln()).sum::<f32>();
+        (error, target.iter().zip(&clamped).map(|(&t, &p)| -t / p).collect())
+      },
+    }
+  }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
 pub struct TrainConfig {
   pub learning_rate: f32,
   pub momentum_rate: Option<f32>,
@@ -56,67 +304,138 @@ pub struct TrainConfig {
   pub epoch_log_period: Option<usize>,
   pub batch_size: Option<f64>,
   pub regularization_param: f32,
+  #[serde(default)]
+  pub loss: Loss,
+  /// Log the fraction of examples whose largest output matches the target's, for classifiers.
+  #[serde(default)]
+  pub report_accuracy: bool,
+  /// Indices into `layers` of the layers whose parameters are kept fixed, e.g. a pretrained
+  /// encoder; 0 is the layer reading the input, unlike the `--layer` flags where 2 is that layer's
+  /// output. Their parameter gradients are never computed and their batch statistics are not
+  /// updated.
+  #[serde(default)]
+  pub frozen_layers: Vec<usize>,
+  /// Factors applied to `learning_rate` and `momentum_rate`, indexed into `layers` like
+  /// `frozen_layers` (0 is the layer reading the input); missing entries are 1.0.
+  #[serde(default)]
+  pub learning_rate_multipliers: Vec<f32>,
+  /// Trains on adversarial examples against the network as it is being trained, for robustness.
+  #[serde(default)]
+  pub adversarial: Option<AdversarialTraining>,
 }
 
-pub type TrainData = Vec<(Vec<f32>, Vec<f32>)>;
+impl TrainConfig {
+  pub fn is_frozen(&self, layer: usize) -> bool {
+    self.frozen_layers.contains(&layer)
+  }
 
-impl Network {
-  pub fn from_definition(defn: &NetworkDefn) -> Network {
-    let mut net = Network {
-      layer_sizes: defn.layers.clone(),
-      activation_coeffs: defn.activation_coeffs.clone(),
-      weights: defn.layers.windows(2).map(|w| DMatrix::new_zeros(w[0], w[1])).collect::<Vec<_>>(),
-      biases: defn.layers.iter().map(|&s| DVector::new_zeros(s)).collect::<Vec<_>>(),
-      activation_fn: match defn.activation_fn.as_str() {
-        "sigmoid" => ActivationFunction::Sigmoid,
-        "tanh" => ActivationFunction::Tanh,
-        "id" => ActivationFunction::Identity,
-        _ => panic!("unrecognized activation function: {}", defn.activation_fn),
-      },
-    };
-    net.activation_coeffs.insert(0, 0.0);
-    net.weights.insert(0, DMatrix::new_zeros(0, 0));
-    net
+  pub fn learning_rate_multiplier(&self, layer: usize) -> f32 {
+    self.learning_rate_multipliers.get(layer).cloned().unwrap_or(1.0)
   }
+}
 
-  pub fn assign_random_weights<R: ::rand::Rng>(&mut self, rng: &mut R) {
-    use rand::distributions::{Normal, IndependentSample};
+/// Index of the largest value, e.g. the class predicted by a classifier's output.
+pub fn argmax(values: &[f32]) -> usize {
+  (1..values.len()).fold(0, |best, it| if values[it] > values[best] { it } else { best })
+}
 
-    let dist = Normal::new(0.0, 0.1);
-    for matrix in &mut self.weights {
-      for weight in matrix.as_mut_vector() {
-        *weight = dist.ind_sample(rng) as f32;
-      }
+fn accuracy(outputs: &[DVector<f32>], targets: &[DVector<f32>]) -> f32 {
+  outputs.iter().zip(targets).filter(|&(o, t)| argmax(&o.at) == argmax(&t.at)).count() as f32 / outputs.len() as f32
+}
+
+pub type TrainData = Vec<(Vec<f32>, Vec<f32>)>;
+
+/// Examples whose inputs are sequences of equally sized steps, as read by recurrent layers.
+pub type SequenceTrainData = Vec<(Vec<Vec<f32>>, Vec<f32>)>;
+
+const MODEL_MAGIC: &'static [u8] = b"FNGR";
+const MODEL_VERSION: u32 = 1;
+
+impl Sequential {
+  pub fn from_definition(defn: &NetworkDefn) -> Result<Network, DefnError> {
+    if defn.layers.is_empty() || (defn.layers.len() == 1 && defn.nodes.is_empty()) {
+      return Err(DefnError::NoLayers);
+    }
+    let expected = defn.layers.len().saturating_sub(1);
+    if defn.activation_coeffs.len() != expected {
+      return Err(DefnError::EntryCount { field: "activation_coeffs", expected: expected, found: defn.activation_coeffs.len() });
+    }
+    // the per-layer options may leave out trailing layers, which then get the defaults
+    if defn.batch_norm.len() > expected {
+      return Err(DefnError::EntryCount { field: "batch_norm", expected: expected, found: defn.batch_norm.len() });
     }
-    for bias_v in &mut self.biases {
-      for b in bias_v.iter_mut() {
-        *b = dist.ind_sample(rng) as f32;
+    if defn.layer_types.len() > expected {
+      return Err(DefnError::EntryCount { field: "layer_types", expected: expected, found: defn.layer_types.len() });
+    }
+
+    let mut layers = Vec::with_capacity(defn.layers.len());
+    for (it, (w, &coeff)) in defn.layers.windows(2).zip(&defn.activation_coeffs).enumerate() {
+      let batch_norm = defn.batch_norm.get(it).cloned().unwrap_or(false);
+      let layer_type = defn.layer_types.get(it).map(|t| t.as_str()).unwrap_or("dense");
+      layers.push(defn.layer(layer_type, w[0], w[1], coeff, batch_norm)?);
+    }
+
+    if !defn.nodes.is_empty() {
+      let input_size = defn.layers[defn.layers.len() - 1];
+      layers.push(LayerKind::Graph(Graph::from_definition(input_size, defn)?));
+    }
+
+    Ok(Sequential { layers: layers })
+  }
+
+  /// Loads a model written by `save`, falling back to the pre-`Sequential` format.
+  pub fn load(path: &str) -> bc::Result<Network> {
+    use std::fs::File;
+    use std::io::{Read, BufReader};
+
+    let mut bytes = Vec::new();
+    BufReader::new(File::open(path)?).read_to_end(&mut bytes)?;
+
+    if bytes.starts_with(MODEL_MAGIC) {
+      let mut rest = &bytes[MODEL_MAGIC.len()..];
+      let version: u32 = bc::deserialize_from(&mut rest, bc::Infinite)?;
+      if version != MODEL_VERSION {
+        return Err(bc::ErrorKind::Custom(format!("unsupported model version {} in {}", version, path)).into());
       }
+      bc::deserialize_from(&mut rest, bc::Infinite)
+    } else {
+      let legacy: LegacyNetwork = bc::deserialize_from(&mut &bytes[..], bc::Infinite)?;
+      Ok(legacy.into())
     }
   }
 
-  fn zero_layers(&self) -> Vec<DVector<f32>> {
-    self.layer_sizes.iter().map(|&sz| DVector::new_zeros(sz)).collect::<Vec<_>>()
+  pub fn save(&self, path: &str) -> bc::Result<()> {
+    use std::fs::File;
+    use std::io::{Write, BufWriter};
+
+    let mut file = BufWriter::new(File::create(path)?);
+    file.write_all(MODEL_MAGIC)?;
+    bc::serialize_into(&mut file, &MODEL_VERSION, bc::Infinite)?;
+    bc::serialize_into(&mut file, self, bc::Infinite)
   }
 
-  fn zero_weights(&self) -> Vec<DMatrix<f32>> {
-    let mut weights = self.layer_sizes.windows(2).map(|w| DMatrix::new_zeros(w[0], w[1])).collect::<Vec<_>>();
-    weights.insert(0, DMatrix::new_zeros(0, 0));
-    weights
+  pub fn layer_sizes(&self) -> Vec<usize> {
+    let mut sizes = self.layers.first().map(|l| vec![l.input_size()]).unwrap_or(Vec::new());
+    sizes.extend(self.layers.iter().map(|l| l.output_size()));
+    sizes
   }
 
-  fn weight_sum(mut delta1: Vec<DMatrix<f32>>, delta2: Vec<DMatrix<f32>>) -> Vec<DMatrix<f32>> {
-    for (dw1, dw2) in delta1.iter_mut().zip(delta2.iter().cloned()) {
-      *dw1 += dw2;
-    }
-    delta1
+  pub fn input_size(&self) -> usize {
+    self.layers[0].input_size()
+  }
+
+  pub fn output_size(&self) -> usize {
+    self.layers.last().unwrap().output_size()
   }
 
-  fn bias_sum(mut bias1: Vec<DVector<f32>>, bias2: Vec<DVector<f32>>) -> Vec<DVector<f32>> {
-    for (dw1, dw2) in bias1.iter_mut().zip(bias2.iter().cloned()) {
-      *dw1 += dw2;
+  pub fn assign_random_weights<R: ::rand::Rng>(&mut self, rng: &mut R) {
+    for layer in &mut self.layers {
+      layer.assign_random_weights(rng);
     }
-    bias1
+  }
+
+  fn zero_updates(&self) -> Vec<Vec<Vec<f32>>> {
+    self.layers.iter().map(|l| l.params().iter().map(|p| vec![0.0; p.len()]).collect()).collect()
   }
 
   pub fn split_data_sequences<R: ::rand::Rng>(rng: &mut R, all_data: TrainData, conf: &TrainConfig) -> (TrainData, TrainData) {
@@ -155,14 +474,21 @@ impl Network {
     (train_data, val_data)
   }
 
-  fn cost(&mut self, output_error: f32, examples: usize, conf: &TrainConfig) -> f32 {
+  fn cost(&self, output_error: f32, examples: usize, conf: &TrainConfig) -> f32 {
     if examples == 0 {
       return 0.0;
     }
     let lambda = conf.regularization_param;
 
+    // averaged over one more matrix than there are layers, like the legacy network's placeholder
+    // input-layer matrix, so that costs stay comparable with it
     output_error +
-    if lambda != 0.0 { conf.regularization_param * self.weights.iter().map(|mat| mat.as_vector().iter().map(|w| w*w).sum::<f32>() / examples as f32).sum::<f32>() / self.weights.len() as f32 } else { 0.0 }
+    if lambda != 0.0 {
+      let weights = self.layers.iter()
+        .flat_map(|l| l.params().into_iter().enumerate().filter(move |&(idx, _)| l.decays(idx)).map(|(_, p)| p))
+        .collect::<Vec<_>>();
+      conf.regularization_param * weights.iter().map(|mat| mat.iter().map(|w| w*w).sum::<f32>() / examples as f32).sum::<f32>() / (self.layers.len() + 1) as f32
+    } else { 0.0 }
   }
 
   pub fn train_autoencoder<T>(&mut self, mut train_batch_factory: T, validation_data: Option<Vec<Vec<f32>>>, conf: &TrainConfig, learning: Option<Arc<AtomicBool>>)
@@ -174,60 +500,96 @@ impl Network {
         learning)
   }
 
+  /// Trains on sequences of steps, e.g. the rows of images. Every step must be as long as the
+  /// first layer reads if it is recurrent; training stops at the first batch that does not fit.
+  pub fn train_sequences<T>(&mut self, mut train_batch_factory: T, validation_data: Option<SequenceTrainData>, conf: &TrainConfig, learning: Option<Arc<AtomicBool>>) -> Result<(), ShapeError>
+      where T: FnMut() -> Option<SequenceTrainData>
+  {
+    fn flatten(data: SequenceTrainData) -> TrainData {
+      data.into_iter().map(|(sequence, output)| (sequence.concat(), output)).collect()
+    }
+
+    fn check(data: &SequenceTrainData, input_size: usize, step_size: Option<usize>) -> Result<(), ShapeError> {
+      for &(ref sequence, _) in data {
+        if let Some(expected) = step_size {
+          if let Some(step) = sequence.iter().find(|s| s.len() != expected) {
+            return Err(ShapeError::StepSize { expected: expected, found: step.len() });
+          }
+        }
+        let found = sequence.iter().map(|s| s.len()).sum();
+        if found != input_size {
+          return Err(ShapeError::InputSize { expected: input_size, found: found });
+        }
+      }
+      Ok(())
+    }
+
+    let input_size = self.input_size();
+    let step_size = match self.layers[0] {
+      LayerKind::Recurrent(ref l) => Some(l.step_size()),
+      _ => None,
+    };
+    if let Some(ref data) = validation_data {
+      check(data, input_size, step_size)?;
+    }
+
+    let mut mismatch = None;
+    self.train(|| match train_batch_factory() {
+      Some(batch) => match check(&batch, input_size, step_size) {
+        Ok(()) => Some(flatten(batch)),
+        Err(e) => {
+          mismatch = Some(e);
+          None
+        },
+      },
+      None => None,
+    }, validation_data.map(flatten), conf, learning);
+    mismatch.map_or(Ok(()), Err)
+  }
+
   pub fn train<T>(&mut self, mut train_batch_factory: T, validation_data: Option<TrainData>, conf: &TrainConfig, learning: Option<Arc<AtomicBool>>)
       where T: FnMut() -> Option<Vec<(Vec<f32>, Vec<f32>)>>
   {
-    use rayon::prelude::*;
-
     let mut epochs_since_validation_improvement = 0usize;
     let mut epoch = 0usize;
-    let mut last_weight_update_sum = self.zero_weights();
-    let mut last_bias_update_sum = self.zero_layers();
+    let mut last_update_sum = self.zero_updates();
     let mut best_known_net = self.clone();
 
     let mut validation_cost = ::std::f32::INFINITY;
 
     let is_validating = validation_data.is_some();
-    let validation_data_dvectors: Option<Vec<_>> = validation_data.map(|v| v.into_iter().map(|(i, o)| (DVector { at: i }, DVector { at: o })).collect());
+    let validation_data_dvectors: Option<(Vec<_>, Vec<_>)> = validation_data.map(|v| v.into_iter().map(|(i, o)| (DVector { at: i }, DVector { at: o })).unzip());
 
     while learning.as_ref().map(|l| l.load(Ordering::SeqCst)).unwrap_or(true) &&
         epochs_since_validation_improvement < conf.sequential_validation_failures_required &&
         conf.max_epochs.map(|max| epoch < max).unwrap_or(true) {
       epoch += 1;
-      if let Some(batch) = train_batch_factory() {
+      if let Some(mut batch) = train_batch_factory() {
+        if let Some(ref adversarial) = conf.adversarial {
+          self.mix_adversarial_examples(&mut batch, adversarial, conf.loss);
+        }
         let batch_len = batch.len();
-        let (weight_update_sum, bias_update_sum, mut train_error) = batch.into_par_iter()
-          .map(|(i, o)| (DVector { at: i }, DVector { at: o}))
-          .map(|(input, output)| {
-            let mut layers = self.zero_layers();
-            *layers.get_mut(0).unwrap() = input.clone();
-            let mut layer_inputs = self.zero_layers();
-            let layers_len = layers.len();
-            self.feed_forward(&mut layers, &mut layer_inputs, layers_len);
-            let out_layer_diff = layers.last().unwrap().clone() - output;
-            let train_error = out_layer_diff.norm_squared() / out_layer_diff.len() as f32;
-            let residual_errors = self.backpropagate(layer_inputs.clone(), out_layer_diff, conf);
-            let updates = self.compute_weight_update(&layers, residual_errors, conf);
-            (updates.0, updates.1, train_error)
-          })
-          .reduce(|| (self.zero_weights(), self.zero_layers(), 0.0),
-            |(a_w, a_b, a_err), (b_w, b_b, b_err)| (Network::weight_sum(a_w, b_w), Network::bias_sum(a_b, b_b), a_err + b_err));
-
-        train_error /= batch_len as f32;
-
-        self.update_weights(&weight_update_sum, &bias_update_sum, &last_weight_update_sum, &last_bias_update_sum, batch_len, conf);
+        let (inputs, outputs): (Vec<_>, Vec<_>) = batch.into_iter().map(|(i, o)| (DVector { at: i }, DVector { at: o })).unzip();
+
+        let layers_len = self.layers.len();
+        let (layers, caches) = self.feed_forward(&inputs, layers_len, true);
+        for (it, (layer, cache)) in self.layers.iter_mut().zip(&caches).enumerate() {
+          if !conf.is_frozen(it) {
+            layer.update_statistics(cache);
+          }
+        }
+        let (errors, out_layer_diff): (Vec<f32>, Vec<_>) = layers.iter().zip(&outputs).map(|(l, o)| conf.loss.error(l, o)).unzip();
+        let train_error = errors.iter().sum::<f32>() / batch_len as f32;
+        let train_accuracy = accuracy(&layers, &outputs);
+        let update_sum = self.compute_weight_update(&caches, out_layer_diff, conf);
+
+        self.update_weights(&update_sum, &last_update_sum, batch_len, conf);
 
         let train_cost = self.cost(train_error, batch_len, conf);
 
-        let validation_error = validation_data_dvectors.as_ref().map(|v| v.par_iter()
-          .map(|&(ref input, ref output)| {
-            let mut layers = self.zero_layers();
-            self.validation_error_of(&mut layers, input, output)
-          })
-          .sum::<f32>()
-          / v.len() as f32);
-        if let Some(verr) = validation_error {
-          let new_validation_cost = self.cost(verr, validation_data_dvectors.as_ref().map(|v| v.len()).unwrap_or(0), conf);
+        let validation_error = validation_data_dvectors.as_ref().map(|&(ref inputs, ref outputs)| self.validation_error_of(inputs, outputs, conf));
+        if let Some((verr, validation_accuracy)) = validation_error {
+          let new_validation_cost = self.cost(verr, validation_data_dvectors.as_ref().map(|v| v.0.len()).unwrap_or(0), conf);
 
           if new_validation_cost < validation_cost {
             epochs_since_validation_improvement = 0;
@@ -238,17 +600,24 @@ impl Network {
           }
 
           if epoch % conf.epoch_log_period.unwrap_or(10) == 0 {
-            println!("#{} - train err: {}, val err: {} (last best: {}, stability: {})", epoch, train_cost, new_validation_cost, validation_cost, epochs_since_validation_improvement);
+            if conf.report_accuracy {
+              println!("#{} - train err: {}, val err: {} (last best: {}, stability: {}), train acc: {}, val acc: {}", epoch, train_cost, new_validation_cost, validation_cost, epochs_since_validation_improvement, train_accuracy, validation_accuracy);
+            } else {
+              println!("#{} - train err: {}, val err: {} (last best: {}, stability: {})", epoch, train_cost, new_validation_cost, validation_cost, epochs_since_validation_improvement);
+            }
           }
         } else {
           if epoch % conf.epoch_log_period.unwrap_or(10) == 0 {
-            println!("#{} - train err: {}", epoch, train_cost);
+            if conf.report_accuracy {
+              println!("#{} - train err: {}, train acc: {}", epoch, train_cost, train_accuracy);
+            } else {
+              println!("#{} - train err: {}", epoch, train_cost);
+            }
           }
         }
 
         if conf.momentum_rate.is_some() {
-          last_weight_update_sum = weight_update_sum;
-          last_bias_update_sum = bias_update_sum;
+          last_update_sum = update_sum;
         }
       } else {
         break;
@@ -259,126 +628,440 @@ impl Network {
     }
   }
 
-  fn compute_weight_update(&self, layers: &[DVector<f32>], delta: Vec<DVector<f32>>, conf: &TrainConfig) -> (Vec<DMatrix<f32>>, Vec<DVector<f32>>) {
-    use na::Outer;
+  /// Parameter gradients of every layer; frozen layers get none. Backpropagation stops at the
+  /// lowest layer that is not frozen.
+  fn compute_weight_update(&self, caches: &[LayerCache], out_layer_diff: Vec<DVector<f32>>, conf: &TrainConfig) -> Vec<Vec<Vec<f32>>> {
+    let lowest_trained = (0..caches.len()).find(|&it| !conf.is_frozen(it)).unwrap_or(caches.len());
+    let mut updates = vec![Vec::new(); lowest_trained];
+    updates.extend(self.backpropagate(caches, out_layer_diff, lowest_trained, conf).1);
+    updates
+  }
 
-    let mut weight_update = self.zero_weights();
-    let mut bias_update = self.zero_layers();
+  /// Returns the mean error and the accuracy over the validation set.
+  fn validation_error_of(&self, inputs: &[DVector<f32>], outputs: &[DVector<f32>], conf: &TrainConfig) -> (f32, f32) {
+    let layers_len = self.layers.len();
+    let layers = self.eval_impl(inputs, layers_len);
 
-    for it in 1..layers.len() {
-      let correction = layers[it-1].outer(&delta[it]);
-      weight_update[it] = correction;
-      bias_update[it] = delta[it].clone();
-    }
+    let error = layers.iter().zip(outputs)
+      .map(|(l, o)| conf.loss.error(l, o).0)
+      .sum::<f32>()
+      / inputs.len() as f32;
+    (error, accuracy(&layers, outputs))
+  }
 
-    (weight_update, bias_update)
+  /// Like `feed_forward`, but drops every layer's cache as soon as the layer has run, so that only
+  /// one layer's intermediate values are held at a time.
+  fn eval_impl(&self, examples: &[DVector<f32>], stop_at: usize) -> Vec<DVector<f32>> {
+    self.layers[..stop_at].iter().fold(examples.to_vec(), |values, l| l.forward(&values, false).0)
   }
 
-  fn validation_error_of(&self, layers: &mut Vec<DVector<f32>>, input: &DVector<f32>, output: &DVector<f32>) -> f32 {
-    debug_assert_eq!(layers[0].len(), input.len());
+  pub fn eval(&self, example: Vec<f32>) -> Vec<f32> {
+    assert_eq!(self.input_size(), example.len());
+    let layers_len = self.layers.len();
+    self.eval_impl(&[DVector { at: example }], layers_len).pop().unwrap().at
+  }
 
-    let layers_len = layers.len();
-    self.eval_impl(layers, input.clone(), layers_len);
-    
-    (output.clone() - layers.last().unwrap().clone()).norm_squared() / layers.last().unwrap().len() as f32
+  /// Evaluates a whole batch of examples at once.
+  pub fn eval_batch(&self, examples: Vec<Vec<f32>>) -> Vec<Vec<f32>> {
+    let layers_len = self.layers.len();
+    self.eval_batch_to_layer(examples, layers_len + 1)
   }
 
-  fn eval_impl(&self, layers: &mut Vec<DVector<f32>>, example: DVector<f32>, stop_at: usize) {
-    layers[0] = example;
-    let mut _li = self.zero_layers();
-    let layers_len = layers.len();
-    self.feed_forward(layers, &mut _li, layers_len);
+  /// Batch counterpart of `eval_to_layer`.
+  pub fn eval_batch_to_layer(&self, examples: Vec<Vec<f32>>, layer: usize) -> Vec<Vec<f32>> {
+    let inputs = examples.into_iter().map(|ex| DVector { at: ex }).collect::<Vec<_>>();
+    self.eval_impl(&inputs, layer - 1).into_iter().map(|out| out.at).collect()
   }
 
-  pub fn eval(&self, example: Vec<f32>) -> Vec<f32> {
-    use na::Iterable;
-    let mut layers = self.zero_layers();
-    assert_eq!(layers[0].len(), example.len());
-    let layers_len = layers.len();    
-    self.eval_impl(&mut layers, DVector { at: example }, layers_len);
-    layers.last().unwrap().iter().cloned().collect()
+  /// Returns the network made of the layers `eval_to_layer` runs to reach `layer`.
+  pub fn truncated(&self, layer: usize) -> Network {
+    assert!(layer >= 2 && layer <= self.layers.len() + 1, "cannot truncate a network of {} layers at layer {}", self.layers.len(), layer);
+    Sequential {
+      layers: self.layers[..(layer - 1)].to_vec(),
+    }
+  }
+
+  /// Returns the network made of the layers following `layer`, taking what `eval_to_layer` computes
+  /// as its input; e.g. the decoder of an autoencoder split at its bottleneck.
+  pub fn layers_from(&self, layer: usize) -> Network {
+    assert!(layer >= 1 && layer <= self.layers.len(), "network of {} layers has no layers after layer {}", self.layers.len(), layer);
+    Sequential {
+      layers: self.layers[(layer - 1)..].to_vec(),
+    }
+  }
+
+  /// Appends a layer reading the current output of the network.
+  pub fn push_layer(&mut self, layer: LayerKind) {
+    assert_eq!(self.output_size(), layer.input_size());
+    self.layers.push(layer);
   }
 
   pub fn eval_to_layer(&self, example: Vec<f32>, layer: usize) -> Vec<f32> {
-    use na::Iterable;
-    let mut layers = self.zero_layers();
-    assert_eq!(layers[0].len(), example.len());
-    self.eval_impl(&mut layers, DVector { at: example }, layer);
-    layers[layer - 1].iter().cloned().collect()
+    assert_eq!(self.input_size(), example.len());
+    self.eval_impl(&[DVector { at: example }], layer - 1).pop().unwrap().at
   }
 
-  fn feed_forward(&self, layers: &mut Vec<DVector<f32>>, layer_inputs: &mut Vec<DVector<f32>>, stop_at: usize) {
-    use na::Iterable;
+  /// Runs the layers following `layer` on a code as returned by `eval_to_layer`, e.g. decodes a
+  /// bottleneck code of an autoencoder.
+  pub fn eval_from_layer(&self, code: Vec<f32>, layer: usize) -> Result<Vec<f32>, ShapeError> {
+    self.eval_batch_from_layer(vec![code], layer).map(|mut out| out.pop().unwrap())
+  }
 
-    for it in 0..(stop_at - 1) {
-      let input = {
-        let mut clone = layers[it].clone();
-        clone *= &self.weights[it + 1];
-        clone
-      };
-      debug_assert_eq!(layers[it + 1].len(), input.len());
-      debug_assert_eq!(layers[it + 1].len(), self.biases[it + 1].len());
-      // println!("layer_inputs is {}, biases is {}", layer_inputs.len(), self.biases.len());
-      layer_inputs[it + 1] = input.iter().zip(self.biases[it + 1].iter()).map(|(&net, &b)| net + b).collect(); 
-      layers[it + 1] = layer_inputs[it + 1].iter().map(|&inp| self.activation_fn.function(inp, self.activation_coeffs[it + 1])).collect();
+  /// Batch counterpart of `eval_from_layer`.
+  pub fn eval_batch_from_layer(&self, codes: Vec<Vec<f32>>, layer: usize) -> Result<Vec<Vec<f32>>, ShapeError> {
+    let sizes = self.layer_sizes();
+    if layer < 1 || layer > sizes.len() {
+      return Err(ShapeError::NoSuchLayer { layer: layer, layers: sizes.len() });
     }
+    if let Some(code) = codes.iter().find(|code| code.len() != sizes[layer - 1]) {
+      return Err(ShapeError::CodeSize { layer: layer, expected: sizes[layer - 1], found: code.len() });
+    }
+
+    let mut values = codes.into_iter().map(|code| DVector { at: code }).collect::<Vec<_>>();
+    for l in &self.layers[(layer - 1)..] {
+      values = l.forward(&values, false).0;
+    }
+    Ok(values.into_iter().map(|out| out.at).collect())
   }
 
-  fn backpropagate(&self, mut layers: Vec<DVector<f32>>, out_layer_diff: DVector<f32>, conf: &TrainConfig) -> Vec<DVector<f32>> {
-    use na::Iterable;
+  /// Value of `unit` of layer `layer` (numbered as in `eval_to_layer`) at `example`, with its
+  /// gradient w.r.t. the input.
+  pub fn unit_input_gradient(&self, example: Vec<f32>, layer: usize, unit: usize) -> (f32, Vec<f32>) {
+    assert_eq!(self.input_size(), example.len());
+    let (mut outputs, caches) = self.feed_forward(&[DVector { at: example }], layer - 1, false);
+    let output = outputs.pop().unwrap();
 
-    for (layer, coeff) in layers.iter_mut().zip(&self.activation_coeffs) {
-      for out in layer.iter_mut() {
-        *out = self.activation_fn.derivative(*out, *coeff);
-      }
+    let mut delta = DVector::new_zeros(output.len());
+    delta[unit] = 1.0;
+    (output[unit], self.backpropagate_inputs(&caches, vec![delta]).pop().unwrap().at)
+  }
+
+  /// Loss of the output for `example` against `target`, with its gradient w.r.t. the input.
+  pub fn input_gradient(&self, example: Vec<f32>, target: Vec<f32>, loss: Loss) -> (f32, Vec<f32>) {
+    assert_eq!(self.input_size(), example.len());
+    let layers_len = self.layers.len();
+    let (mut outputs, caches) = self.feed_forward(&[DVector { at: example }], layers_len, false);
+
+    let (error, diff) = loss.error(&outputs.pop().unwrap(), &DVector { at: target });
+    (error, self.backpropagate_inputs(&caches, vec![diff]).pop().unwrap().at)
+  }
+
+  /// `jacobian[o][i]` is the derivative of output unit `o` w.r.t. input `i` at `example`.
+  pub fn jacobian(&self, example: Vec<f32>) -> Vec<Vec<f32>> {
+    assert_eq!(self.input_size(), example.len());
+    let layers_len = self.layers.len();
+    let caches = self.feed_forward(&[DVector { at: example }], layers_len, false).1;
+
+    (0..self.output_size())
+      .map(|unit| {
+        let mut delta = DVector::new_zeros(self.output_size());
+        delta[unit] = 1.0;
+        self.backpropagate_inputs(&caches, vec![delta]).pop().unwrap().at
+      })
+      .collect()
+  }
+
+  /// The layer with the fewest units, e.g. the code layer of an autoencoder.
+  pub fn bottleneck_layer(&self) -> usize {
+    let sizes = self.layer_sizes();
+    (1..sizes.len()).fold(0, |best, it| if sizes[it] < sizes[best] { it } else { best }) + 1
+  }
+
+  /// Runs the first `stop_at` layers over a batch, returning their output and the per-layer caches
+  /// needed for backpropagation.
+  fn feed_forward(&self, inputs: &[DVector<f32>], stop_at: usize, train: bool) -> (Vec<DVector<f32>>, Vec<LayerCache>) {
+    let mut caches = Vec::with_capacity(stop_at);
+    let mut layer = inputs.to_vec();
+
+    for l in &self.layers[..stop_at] {
+      let (out, cache) = l.forward(&layer, train);
+      caches.push(cache);
+      layer = out;
     }
 
-    let mut delta = self.zero_layers();
+    (layer, caches)
+  }
+
+  /// Backpropagates down to the input of layer `stop_at`, returning the error gradient w.r.t. that
+  /// input and the parameter gradients of the layers from `stop_at` up. Frozen layers get empty
+  /// parameter gradients.
+  fn backpropagate(&self, caches: &[LayerCache], out_layer_diff: Vec<DVector<f32>>, stop_at: usize, conf: &TrainConfig) -> (Vec<DVector<f32>>, Vec<Vec<Vec<f32>>>) {
+    let mut delta = out_layer_diff;
+    let mut updates = Vec::with_capacity(caches.len() - stop_at);
 
-    *delta.last_mut().unwrap() = out_layer_diff
-      .iter()
-      .zip(layers.last().unwrap().iter())
-      .map(|(e, fz)| e * fz)
-      .collect();
-    for it in (0..(layers.len() - 1)).rev() {
-      let next_delta: DVector<f32> = &self.weights[it + 1] * &delta[it + 1];
-      debug_assert_eq!(next_delta.len(), delta[it].len());
-      delta[it] = next_delta.iter().zip(layers[it].iter()).map(|(&d, x)| d * *x).collect();
+    for (it, (layer, cache)) in self.layers.iter().zip(caches).enumerate().skip(stop_at).rev() {
+      if conf.is_frozen(it) {
+        delta = layer.backward_inputs(cache, &delta);
+        updates.push(Vec::new());
+      } else {
+        let (input_delta, update) = layer.backward(cache, &delta);
+        updates.push(update);
+        delta = input_delta;
+      }
     }
+    updates.reverse();
 
-    delta
+    (delta, updates)
   }
 
-  fn update_weights(&mut self, weight_update_sum: &[DMatrix<f32>], bias_update_sum: &[DVector<f32>], last_weight_update_sum: &[DMatrix<f32>], last_bias_update_sum: &[DVector<f32>], examples: usize, conf: &TrainConfig) {
-    use na::Iterable;
+  /// Backpropagates through the layers `caches` were recorded for, without parameter gradients,
+  /// returning the gradient w.r.t. the network input.
+  fn backpropagate_inputs(&self, caches: &[LayerCache], out_layer_diff: Vec<DVector<f32>>) -> Vec<DVector<f32>> {
+    self.layers.iter().zip(caches).rev().fold(out_layer_diff, |delta, (layer, cache)| layer.backward_inputs(cache, &delta))
+  }
 
-    for it in 0..self.weights.len() {
-      for (w, dw) in self.weights[it].as_mut_vector().iter_mut().zip(weight_update_sum[it].as_vector()) {
-        if it == 1 {
-          *w *= 1.0 - conf.regularization_param * conf.learning_rate / examples as f32;
+  fn update_weights(&mut self, update_sum: &[Vec<Vec<f32>>], last_update_sum: &[Vec<Vec<f32>>], examples: usize, conf: &TrainConfig) {
+    let examples = examples as f32;
+
+    for (it, layer) in self.layers.iter_mut().enumerate() {
+      if conf.is_frozen(it) {
+        continue;
+      }
+      let multiplier = conf.learning_rate_multiplier(it);
+      let learning_rate = conf.learning_rate * multiplier;
+      // only the first layer's weights decay, as in the legacy network
+      let decays = (0..update_sum[it].len()).map(|idx| it == 0 && layer.decays(idx)).collect::<Vec<_>>();
+
+      for (idx, param) in layer.params_mut().into_iter().enumerate() {
+        for (w, dw) in param.iter_mut().zip(&update_sum[it][idx]) {
+          if decays[idx] {
+            *w *= 1.0 - conf.regularization_param * learning_rate / examples;
+          }
+          *w -= dw / examples * learning_rate;
+        }
+
+        if let Some(momentum) = conf.momentum_rate {
+          for (w, dw) in param.iter_mut().zip(&last_update_sum[it][idx]) {
+            *w -= dw / examples * momentum * multiplier;
+          }
         }
-        *w -= dw / examples as f32 * conf.learning_rate;
       }
     }
+  }
+}
+
+impl From<LegacyNetwork> for Sequential {
+  fn from(old: LegacyNetwork) -> Sequential {
+    Sequential {
+      layers: (1..old.layer_sizes.len())
+        .map(|it| LayerKind::Dense(Dense {
+          weights: old.weights[it].clone(),
+          biases: old.biases[it].clone(),
+          activation_fn: old.activation_fn,
+          activation_coeffs: DVector { at: vec![old.activation_coeffs[it]] },
+          learn_coeffs: false,
+          batch_norm: None,
+        }))
+        .collect(),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use rand::{SeedableRng, XorShiftRng};
+
+  use super::*;
+
+  fn network(json: &str) -> Network {
+    let mut net = Network::from_definition(&::sj::from_str(json).unwrap()).unwrap();
+    net.assign_random_weights(&mut XorShiftRng::from_seed([1, 2, 3, 4]));
+    net
+  }
+
+  fn train_config() -> TrainConfig {
+    ::sj::from_str(r#"{"learning_rate": 0.1, "momentum_rate": null, "validation_ratio": 0.1,
+      "sequential_validation_failures_required": 5, "max_epochs": 1, "epoch_log_period": 1,
+      "batch_size": 0.01, "regularization_param": 0.0}"#).unwrap()
+  }
+
+  #[test]
+  fn frozen_layers_backpropagate_inputs_only() {
+    let net = network(r#"{"layers": [6, 3, 3], "activation_coeffs": [1, 1], "activation_fn": "tanh",
+      "layer_types": ["rnn", "gru"], "sequence_steps": 3, "nodes": [
+        {"name": "a", "inputs": ["input"], "size": 4, "batch_norm": true},
+        {"name": "b", "inputs": ["a", "input"], "merge": "concat", "size": 2}]}"#);
+    let inputs = (0..3).map(|ex| DVector::from_fn(6, |i| ((ex * 6 + i) as f32 * 0.37).sin())).collect::<Vec<_>>();
+    let layers_len = net.layers.len();
+    let (outputs, caches) = net.feed_forward(&inputs, layers_len, true);
+
+    let mut conf = train_config();
+    let (deltas, updates) = net.backpropagate(&caches, outputs.clone(), 0, &conf);
+    assert!(updates.iter().all(|u| !u.is_empty()));
+
+    conf.frozen_layers = (0..layers_len).collect();
+    let (frozen_deltas, frozen_updates) = net.backpropagate(&caches, outputs, 0, &conf);
+    assert!(frozen_updates.iter().all(|u| u.is_empty()));
+    for (d, f) in deltas.iter().zip(&frozen_deltas) {
+      for (d, f) in d.at.iter().zip(&f.at) {
+        assert!((d - f).abs() < 1e-6, "{} != {}", d, f);
+      }
+    }
+  }
+
+  #[test]
+  fn legacy_models_still_load() {
+    use std::fs::File;
+
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/Model-edgedetectors.bc");
+    let legacy: LegacyNetwork = bc::deserialize_from(&mut File::open(path).unwrap(), bc::Infinite).unwrap();
+    let net = Network::load(path).unwrap();
+    assert_eq!(net.layer_sizes(), legacy.layer_sizes);
+    assert_eq!(net.layer_sizes(), vec![196, 100, 196]);
+
+    // the legacy forward pass, skipping the placeholder matrix of the input layer
+    let input = (0..196).map(|i| (i as f32 * 0.37).sin().abs()).collect::<Vec<f32>>();
+    let mut expected = input.clone();
+    for it in 1..legacy.layer_sizes.len() {
+      expected = (0..legacy.layer_sizes[it])
+        .map(|u| {
+          let net_input = (0..expected.len()).map(|i| expected[i] * legacy.weights[it][(i, u)]).sum::<f32>() + legacy.biases[it][u];
+          legacy.activation_fn.function(net_input, legacy.activation_coeffs[it])
+        })
+        .collect();
+    }
+    for (found, expected) in net.eval(input).iter().zip(&expected) {
+      assert!((found - expected).abs() < 1e-5, "{} != {}", found, expected);
+    }
+  }
+
+  #[test]
+  fn definitions_need_layers() {
+    for json in &[r#"{"layers": [5], "activation_coeffs": [], "activation_fn": "tanh"}"#,
+        r#"{"layers": [], "activation_coeffs": [], "activation_fn": "tanh", "nodes": [{"name": "a", "inputs": ["input"], "size": 4}]}"#] {
+      match Network::from_definition(&::sj::from_str(json).unwrap()) {
+        Err(DefnError::NoLayers) => {},
+        other => panic!("unexpected result {:?}", other.map(|n| n.layer_sizes())),
+      }
+    }
+  }
 
-    for it in 0..self.biases.len() {
-      for (b, db) in self.biases[it].iter_mut().zip(bias_update_sum[it].iter()) {
-        *b -= db / examples as f32 * conf.learning_rate;
+  #[test]
+  fn per_layer_options_are_checked() {
+    let result = |options: &str| {
+      let json = format!(r#"{{"layers": [6, 4, 2], "activation_coeffs": [1, 1], "activation_fn": "tanh", {}}}"#, options);
+      Network::from_definition(&::sj::from_str(&json).unwrap()).map(|n| n.layer_sizes())
+    };
+    assert!(result(r#""batch_norm": [true], "layer_types": ["dense"]"#).is_ok());
+    match result(r#""batch_norm": [true, false, true]"#) {
+      Err(DefnError::EntryCount { field: "batch_norm", expected: 2, found: 3 }) => {},
+      other => panic!("unexpected result {:?}", other),
+    }
+    match result(r#""layer_types": ["dense", "softmax", "dense"]"#) {
+      Err(DefnError::EntryCount { field: "layer_types", expected: 2, found: 3 }) => {},
+      other => panic!("unexpected result {:?}", other),
+    }
+    for layer_type in &["maxout", "rnn", "softmax"] {
+      match result(&format!(r#""batch_norm": [true], "layer_types": ["{}"]"#, layer_type)) {
+        Err(DefnError::BatchNorm(ref found)) if found == layer_type => {},
+        other => panic!("unexpected result {:?}", other),
+      }
+    }
+  }
+
+  #[test]
+  fn eval_from_layer_continues_eval_to_layer() {
+    let net = network(r#"{"layers": [5, 4, 3, 2], "activation_coeffs": [1, 1, 1], "activation_fn": "sigmoid"}"#);
+    let input = vec![0.1, 0.9, 0.4, 0.0, 0.7];
+    let output = net.eval(input.clone());
+    for layer in 1..(net.layers.len() + 2) {
+      let resumed = net.eval_from_layer(net.eval_to_layer(input.clone(), layer), layer).unwrap();
+      for (r, o) in resumed.iter().zip(&output) {
+        assert!((r - o).abs() < 1e-6, "layer {}: {} != {}", layer, r, o);
       }
     }
+  }
 
-    if let Some(momentum) = conf.momentum_rate {
-      for it in 0..self.weights.len() {
-        for (w, dw) in self.weights[it].as_mut_vector().iter_mut().zip(last_weight_update_sum[it].as_vector()) {
-          *w -= dw / examples as f32 * momentum;
+  #[test]
+  fn eval_from_layer_rejects_bad_shapes() {
+    let net = network(r#"{"layers": [5, 4, 3, 2], "activation_coeffs": [1, 1, 1], "activation_fn": "sigmoid"}"#);
+    match net.eval_from_layer(vec![0.5; 3], 2) {
+      Err(ShapeError::CodeSize { layer: 2, expected: 4, found: 3 }) => {},
+      other => panic!("unexpected result {:?}", other),
+    }
+    for &layer in &[0, net.layers.len() + 2] {
+      match net.eval_from_layer(vec![0.5; 4], layer) {
+        Err(ShapeError::NoSuchLayer { layers: 4, .. }) => {},
+        other => panic!("unexpected result {:?} for layer {}", other, layer),
+      }
+    }
+  }
+
+  #[test]
+  fn input_gradients_match_finite_differences() {
+    let mut net = network(r#"{"layers": [6, 5, 4, 3], "activation_coeffs": [1, 1, 1], "activation_fn": "sigmoid",
+      "layer_types": ["dense", "maxout", "softmax"], "maxout_pieces": 3}"#);
+    // the initial weights are too small for the gradients to stand out from the tolerance
+    for layer in &mut net.layers {
+      for params in layer.params_mut() {
+        for w in params.iter_mut() {
+          *w *= 10.0;
         }
       }
+    }
+    let input = vec![0.1, 0.9, 0.4, 0.0, 0.7, 0.3];
+    let target = ::mnist::one_hot(2, 3);
+    let loss = |x: &[f32]| Loss::CrossEntropy.error(&DVector { at: net.eval(x.to_vec()) }, &DVector { at: target.clone() }).0;
+    let h = 1e-3;
+    let shifted = |i: usize, by: f32| {
+      let mut x = input.clone();
+      x[i] += by;
+      x
+    };
 
-      for it in 0..self.biases.len() {
-        for (b, db) in self.biases[it].iter_mut().zip(last_bias_update_sum[it].iter()) {
-          *b -= db / examples as f32 * momentum;
+    let gradient = net.input_gradient(input.clone(), target.clone(), Loss::CrossEntropy).1;
+    let jacobian = net.jacobian(input.clone());
+    for i in 0..input.len() {
+      let numeric = (loss(&shifted(i, h)) - loss(&shifted(i, -h))) / (2.0 * h);
+      assert!((numeric - gradient[i]).abs() < 2e-3, "loss gradient {}: {} != {}", i, numeric, gradient[i]);
+
+      let (plus, minus) = (net.eval(shifted(i, h)), net.eval(shifted(i, -h)));
+      for o in 0..plus.len() {
+        let numeric = (plus[o] - minus[o]) / (2.0 * h);
+        assert!((numeric - jacobian[o][i]).abs() < 2e-3, "jacobian {} {}: {} != {}", o, i, numeric, jacobian[o][i]);
+      }
+    }
+  }
+
+  #[test]
+  fn accuracy_compares_the_most_active_units() {
+    assert_eq!(argmax(&[0.1, 0.7, 0.7, 0.2]), 1);
+    let outputs = vec![DVector { at: vec![0.2, 0.8] }, DVector { at: vec![0.6, 0.4] }, DVector { at: vec![0.1, 0.9] }];
+    let targets = [1, 1, 0].iter().map(|&lbl| DVector { at: ::mnist::one_hot(lbl, 2) }).collect::<Vec<_>>();
+    assert!((accuracy(&outputs, &targets) - 1.0 / 3.0).abs() < 1e-6);
+  }
+
+  #[test]
+  fn sequences_must_fit_the_recurrent_layer() {
+    let mut net = network(r#"{"layers": [6, 3, 2], "activation_coeffs": [1, 1], "activation_fn": "tanh",
+      "layer_types": ["rnn", "softmax"], "sequence_steps": 3}"#);
+    let conf = train_config();
+    let sequence = |steps: &[usize]| vec![(steps.iter().map(|&len| vec![0.5; len]).collect::<Vec<_>>(), vec![1.0, 0.0])];
+
+    assert!(net.train_sequences(|| Some(sequence(&[2, 2, 2])), None, &conf, None).is_ok());
+    match net.train_sequences(|| Some(sequence(&[3, 3])), None, &conf, None) {
+      Err(ShapeError::StepSize { expected: 2, found: 3 }) => {},
+      other => panic!("unexpected result {:?}", other),
+    }
+    match net.train_sequences(|| Some(sequence(&[2, 2])), Some(sequence(&[2, 2])), &conf, None) {
+      Err(ShapeError::InputSize { expected: 6, found: 4 }) => {},
+      other => panic!("unexpected result {:?}", other),
+    }
+  }
+
+  #[test]
+  fn activation_derivatives_match_finite_differences() {
+    let h = 1e-3;
+    for &f in &[ActivationFunction::Sigmoid, ActivationFunction::Tanh, ActivationFunction::Identity] {
+      for &coeff in &[0.5, 1.0, 2.0] {
+        for &x in &[-1.5, -0.3, 0.0, 0.4, 2.0] {
+          let numeric = (f.function(x + h, coeff) - f.function(x - h, coeff)) / (2.0 * h);
+          assert!((numeric - f.derivative(x, coeff)).abs() < 1e-3, "{:?} at {} with coeff {}", f, x, coeff);
+
+          let numeric = (f.function(x, coeff + h) - f.function(x, coeff - h)) / (2.0 * h);
+          assert!((numeric - f.coeff_derivative(x, coeff)).abs() < 1e-3, "{:?} coeff at {} with coeff {}", f, x, coeff);
         }
       }
     }
   }
-}
\ No newline at end of file
+}
@@ -0,0 +1,156 @@
+/// Principal component analysis: `components` are the unit eigenvectors of the data covariance
+/// with the largest eigenvalues, in decreasing order of the variance they explain.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Pca {
+  pub mean: Vec<f32>,
+  pub components: Vec<Vec<f32>>,
+  /// Variance of the data along every component.
+  pub variances: Vec<f32>,
+  pub total_variance: f32,
+}
+
+impl Pca {
+  /// Fits `components` principal components by eigendecomposition of the covariance of `data`.
+  pub fn fit(data: &[Vec<f32>], components: usize) -> Pca {
+    use rayon::prelude::*;
+
+    let dim = data[0].len();
+    assert!(components <= dim, "cannot take {} components of {}-dimensional data", components, dim);
+    let n = data.len() as f64;
+
+    let mean = (0..dim).map(|i| data.iter().map(|x| x[i] as f64).sum::<f64>() / n).collect::<Vec<_>>();
+    let covariance = data.par_iter()
+      .fold(|| vec![0.0; dim * dim], |mut acc, x| {
+        let centered = x.iter().zip(&mean).map(|(&x, m)| x as f64 - m).collect::<Vec<_>>();
+        for i in 0..dim {
+          for j in i..dim {
+            acc[i * dim + j] += centered[i] * centered[j];
+          }
+        }
+        acc
+      })
+      .reduce(|| vec![0.0; dim * dim], |mut a, b| {
+        for (a, b) in a.iter_mut().zip(b) {
+          *a += b;
+        }
+        a
+      });
+    let mut covariance = covariance.into_iter().map(|c| c / (n - 1.0).max(1.0)).collect::<Vec<_>>();
+    for i in 0..dim {
+      for j in 0..i {
+        covariance[i * dim + j] = covariance[j * dim + i];
+      }
+    }
+
+    let (values, vectors) = symmetric_eigen(covariance, dim);
+    let mut order = (0..dim).collect::<Vec<_>>();
+    order.sort_by(|&a, &b| values[b].partial_cmp(&values[a]).unwrap());
+
+    Pca {
+      mean: mean.into_iter().map(|m| m as f32).collect(),
+      components: order[..components].iter().map(|&c| (0..dim).map(|i| vectors[i * dim + c] as f32).collect()).collect(),
+      variances: order[..components].iter().map(|&c| values[c].max(0.0) as f32).collect(),
+      total_variance: values.iter().map(|v| v.max(0.0)).sum::<f64>() as f32,
+    }
+  }
+
+  /// Coordinates of `x` along the components.
+  pub fn project(&self, x: &[f32]) -> Vec<f32> {
+    self.components.iter()
+      .map(|c| c.iter().zip(x.iter().zip(&self.mean)).map(|(c, (x, m))| c * (x - m)).sum())
+      .collect()
+  }
+
+  /// The point of the data space with given coordinates along the components.
+  pub fn reconstruct(&self, code: &[f32]) -> Vec<f32> {
+    let mut x = self.mean.clone();
+    for (c, &weight) in self.components.iter().zip(code) {
+      for (x, c) in x.iter_mut().zip(c) {
+        *x += weight * c;
+      }
+    }
+    x
+  }
+
+  /// Fraction of the data variance captured by the components.
+  pub fn explained_variance_ratio(&self) -> f32 {
+    self.variances.iter().sum::<f32>() / self.total_variance
+  }
+}
+
+/// Eigenvalues of the `n x n` symmetric row-major matrix `a`, with the eigenvectors as the
+/// columns of the returned row-major matrix, computed by cyclic Jacobi rotations.
+fn symmetric_eigen(mut a: Vec<f64>, n: usize) -> (Vec<f64>, Vec<f64>) {
+  let mut v = (0..(n * n)).map(|it| if it / n == it % n { 1.0 } else { 0.0 }).collect::<Vec<f64>>();
+  let scale = a.iter().map(|x| x * x).sum::<f64>();
+
+  for _ in 0..100 {
+    let off_diagonal = (0..n).flat_map(|i| (0..n).filter(move |&j| j != i).map(move |j| (i, j)))
+      .map(|(i, j)| a[i * n + j] * a[i * n + j])
+      .sum::<f64>();
+    if off_diagonal <= 1e-22 * scale {
+      break;
+    }
+
+    for p in 0..n {
+      for q in (p + 1)..n {
+        let apq = a[p * n + q];
+        if apq == 0.0 {
+          continue;
+        }
+        // rotation zeroing a[p][q]
+        let theta = (a[q * n + q] - a[p * n + p]) / (2.0 * apq);
+        let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+        let c = 1.0 / (t * t + 1.0).sqrt();
+        let s = t * c;
+
+        for k in 0..n {
+          let (akp, akq) = (a[k * n + p], a[k * n + q]);
+          a[k * n + p] = c * akp - s * akq;
+          a[k * n + q] = s * akp + c * akq;
+        }
+        for k in 0..n {
+          let (apk, aqk) = (a[p * n + k], a[q * n + k]);
+          a[p * n + k] = c * apk - s * aqk;
+          a[q * n + k] = s * apk + c * aqk;
+        }
+        for k in 0..n {
+          let (vkp, vkq) = (v[k * n + p], v[k * n + q]);
+          v[k * n + p] = c * vkp - s * vkq;
+          v[k * n + q] = s * vkp + c * vkq;
+        }
+      }
+    }
+  }
+
+  ((0..n).map(|i| a[i * n + i]).collect(), v)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::symmetric_eigen;
+
+  #[test]
+  fn jacobi_eigen_decomposition() {
+    let a = vec![
+      2.0, 1.0, 0.0,
+      1.0, 2.0, 0.0,
+      0.0, 0.0, 5.0,
+    ];
+    let (values, vectors) = symmetric_eigen(a.clone(), 3);
+
+    let mut sorted = values.clone();
+    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    for (found, expected) in sorted.iter().zip(&[1.0, 3.0, 5.0]) {
+      assert!((found - expected).abs() < 1e-9, "eigenvalue {} instead of {}", found, expected);
+    }
+    // every column v is a unit vector with a * v = value * v
+    for (col, value) in values.iter().enumerate() {
+      assert!(((0..3).map(|k| vectors[k * 3 + col] * vectors[k * 3 + col]).sum::<f64>() - 1.0).abs() < 1e-9);
+      for row in 0..3 {
+        let av = (0..3).map(|k| a[row * 3 + k] * vectors[k * 3 + col]).sum::<f64>();
+        assert!((av - value * vectors[row * 3 + col]).abs() < 1e-9);
+      }
+    }
+  }
+}
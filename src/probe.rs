@@ -0,0 +1,82 @@
+use std::{error, fmt};
+
+/// k-nearest-neighbour classifier over fixed feature vectors, e.g. the codes of an encoder.
+pub struct KNearest {
+  pub codes: Vec<Vec<f32>>,
+  pub labels: Vec<usize>,
+  pub k: usize,
+}
+
+impl KNearest {
+  pub fn new(codes: Vec<Vec<f32>>, labels: Vec<usize>, k: usize) -> Result<KNearest, KNearestError> {
+    if k == 0 {
+      return Err(KNearestError::NoNeighbours);
+    }
+    if codes.len() != labels.len() {
+      return Err(KNearestError::LabelCount { codes: codes.len(), labels: labels.len() });
+    }
+    Ok(KNearest { codes: codes, labels: labels, k: k })
+  }
+
+  /// Majority vote of the `k` nearest codes by euclidean distance; ties go to the class whose
+  /// nearest member is closest.
+  pub fn predict(&self, code: &[f32]) -> usize {
+    let mut nearest: Vec<(f32, usize)> = Vec::with_capacity(self.k + 1);
+    for (other, &label) in self.codes.iter().zip(&self.labels) {
+      let dist = other.iter().zip(code).map(|(a, b)| (a - b) * (a - b)).sum::<f32>();
+      if nearest.len() < self.k || dist < nearest[nearest.len() - 1].0 {
+        let pos = nearest.iter().position(|&(d, _)| dist < d).unwrap_or(nearest.len());
+        nearest.insert(pos, (dist, label));
+        nearest.truncate(self.k);
+      }
+    }
+
+    let classes = self.labels.iter().cloned().max().map(|m| m + 1).unwrap_or(0);
+    let mut votes = vec![0; classes];
+    for &(_, label) in &nearest {
+      votes[label] += 1;
+    }
+    let most = votes.iter().cloned().max().unwrap_or(0);
+    nearest.iter().map(|&(_, label)| label).find(|&label| votes[label] == most).unwrap_or(0)
+  }
+
+  pub fn predict_all(&self, codes: &[Vec<f32>]) -> Vec<usize> {
+    use rayon::prelude::*;
+
+    codes.par_iter().map(|code| self.predict(code)).collect()
+  }
+}
+
+#[derive(Debug)]
+pub enum KNearestError {
+  NoNeighbours,
+  LabelCount { codes: usize, labels: usize },
+}
+
+impl fmt::Display for KNearestError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      &KNearestError::NoNeighbours => write!(f, "k-NN needs at least one neighbour to vote"),
+      &KNearestError::LabelCount { codes, labels } => write!(f, "{} codes but {} labels", codes, labels),
+    }
+  }
+}
+
+impl error::Error for KNearestError {
+  fn description(&self) -> &str {
+    "invalid k-NN classifier"
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn rejects_classifiers_without_neighbours() {
+    assert!(KNearest::new(vec![vec![0.0]], vec![0], 0).is_err());
+    assert!(KNearest::new(vec![vec![0.0]], vec![0, 1], 1).is_err());
+    let knn = KNearest::new(vec![vec![0.0], vec![1.0]], vec![0, 1], 1).unwrap();
+    assert_eq!(knn.predict(&[0.9]), 1);
+  }
+}
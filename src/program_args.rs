@@ -1,4 +1,4 @@
-use clap::{Arg, ArgMatches, SubCommand, App};
+use clap::{Arg, ArgGroup, ArgMatches, SubCommand, App};
 
 pub fn get<'a>() -> ArgMatches<'a> {
   App::new("finge-rs")
@@ -34,7 +34,44 @@ pub fn get<'a>() -> ArgMatches<'a> {
         .takes_value(true)
         .default_value("./data/")
         .help("path to directory with training data"))
+      .arg(Arg::with_name("task")
+        .long("task")
+        .short("t")
+        .takes_value(true)
+        .possible_values(&["autoencode", "classify", "classify-rows"])
+        .default_value("autoencode")
+        .help("whether to reconstruct the images or predict their labels, optionally from full-size images fed one row per step; classifiers always use the cross-entropy loss"))
       .about("train a model"))
+    .subcommand(SubCommand::with_name("fine-tune")
+      .arg(Arg::with_name("config")
+        .long("config")
+        .short("c")
+        .takes_value(true)
+        .default_value("Fingers.json")
+        .help("training configuration file"))
+      .arg(Arg::with_name("model")
+        .long("model")
+        .short("m")
+        .takes_value(true)
+        .default_value("Model.bc")
+        .help("pretrained model whose layers are used as the encoder"))
+      .arg(Arg::with_name("layer")
+        .long("layer")
+        .short("l")
+        .takes_value(true)
+        .required(true)
+        .help("layer of the pretrained model whose output feeds the classification head, numbered as in `inspect`'s layer sizes"))
+      .arg(Arg::with_name("freeze")
+        .long("freeze")
+        .short("f")
+        .help("keep the encoder layers fixed and train only the head"))
+      .arg(Arg::with_name("output")
+        .long("output")
+        .short("o")
+        .takes_value(true)
+        .default_value("Classifier.bc")
+        .help("output file for the classifier"))
+      .about("train a softmax classifier on top of a pretrained encoder"))
     .subcommand(SubCommand::with_name("test")
       .arg(Arg::with_name("model")
         .long("model")
@@ -47,7 +84,348 @@ pub fn get<'a>() -> ArgMatches<'a> {
         .short("d")
         .takes_value(true)
         .default_value("./test_data/")
-        .help("path to directory with test data")))
+        .help("path to directory with test data"))
+      .arg(Arg::with_name("top_k")
+        .long("top-k")
+        .short("k")
+        .takes_value(true)
+        .default_value("3")
+        .help("number of best guesses counted by the top-k accuracy"))
+      .arg(Arg::with_name("json")
+        .long("json")
+        .takes_value(true)
+        .help("if defined, also writes the report to given JSON file"))
+      .about("evaluate a classifier on the t10k test set"))
+    .subcommand(SubCommand::with_name("probe")
+      .arg(Arg::with_name("config")
+        .long("config")
+        .short("c")
+        .takes_value(true)
+        .default_value("Fingers.json")
+        .help("training configuration of the logistic regression probe"))
+      .arg(Arg::with_name("model")
+        .long("model")
+        .short("m")
+        .takes_value(true)
+        .default_value("Model.bc")
+        .help("model whose features are probed"))
+      .arg(Arg::with_name("layer")
+        .long("layer")
+        .short("l")
+        .takes_value(true)
+        .required(true)
+        .help("layer whose output is used as the features, numbered as in `inspect`'s layer sizes"))
+      .arg(Arg::with_name("neighbours")
+        .long("neighbours")
+        .short("k")
+        .takes_value(true)
+        .default_value("5")
+        .validator(|k| match k.parse::<usize>() {
+          Ok(k) if k >= 1 => Ok(()),
+          _ => Err(String::from("the number of neighbours must be a positive integer")),
+        })
+        .help("number of neighbours voting in the k-NN classifier"))
+      .arg(Arg::with_name("limit")
+        .long("limit")
+        .short("n")
+        .takes_value(true)
+        .help("if defined, only the first given number of training images are used"))
+      .arg(Arg::with_name("data_dir")
+        .long("data-dir")
+        .short("d")
+        .takes_value(true)
+        .default_value("./test_data/")
+        .help("path to directory with test data"))
+      .about("measure how well a layer's features separate the digits with a linear probe and k-NN"))
+    .subcommand(SubCommand::with_name("cluster")
+      .arg(Arg::with_name("model")
+        .long("model")
+        .short("m")
+        .takes_value(true)
+        .default_value("Model.bc")
+        .help("model whose codes are clustered"))
+      .arg(Arg::with_name("layer")
+        .long("layer")
+        .short("l")
+        .takes_value(true)
+        .required(true)
+        .help("layer whose output is clustered, numbered as in `inspect`'s layer sizes"))
+      .arg(Arg::with_name("clusters")
+        .long("clusters")
+        .short("k")
+        .takes_value(true)
+        .default_value("10")
+        .help("number of clusters"))
+      .arg(Arg::with_name("restarts")
+        .long("restarts")
+        .short("r")
+        .takes_value(true)
+        .default_value("8")
+        .help("number of k-means++ initialisations tried"))
+      .arg(Arg::with_name("max_iterations")
+        .long("max-iterations")
+        .takes_value(true)
+        .default_value("100")
+        .help("iteration limit of every k-means run"))
+      .arg(Arg::with_name("limit")
+        .long("limit")
+        .short("n")
+        .takes_value(true)
+        .help("if defined, only the first given number of training images are clustered"))
+      .arg(Arg::with_name("dir")
+        .long("dir")
+        .short("d")
+        .takes_value(true)
+        .default_value("./clusters/")
+        .help("decoded cluster centres dump directory"))
+      .about("k-means cluster the codes of a layer and compare the clusters with the digit labels"))
+    .subcommand(SubCommand::with_name("evaluate")
+      .arg(Arg::with_name("model")
+        .long("model")
+        .short("m")
+        .takes_value(true)
+        .default_value("Model.bc")
+        .help("autoencoder to be evaluated"))
+      .arg(Arg::with_name("data_dir")
+        .long("data-dir")
+        .short("d")
+        .takes_value(true)
+        .default_value("./test_data/")
+        .help("path to directory with test data"))
+      .arg(Arg::with_name("worst")
+        .long("worst")
+        .short("n")
+        .takes_value(true)
+        .default_value("20")
+        .help("number of worst reconstructions to be saved"))
+      .arg(Arg::with_name("dir")
+        .long("dir")
+        .takes_value(true)
+        .default_value("./worst/")
+        .help("worst reconstructions dump directory"))
+      .about("measure the reconstruction error of an autoencoder on the t10k test set"))
+    .subcommand(SubCommand::with_name("score")
+      .arg(Arg::with_name("model")
+        .long("model")
+        .short("m")
+        .takes_value(true)
+        .default_value("Model.bc")
+        .help("autoencoder whose reconstruction error is the anomaly score"))
+      .arg(Arg::with_name("data")
+        .takes_value(true)
+        .required(true)
+        .value_name("DATA")
+        .help("IDX image file or directory of image files to be scored"))
+      .arg(Arg::with_name("validation")
+        .long("validation")
+        .short("v")
+        .takes_value(true)
+        .required(true)
+        .help("IDX image file or directory of image files without anomalies, for calibrating the threshold"))
+      .arg(Arg::with_name("false_positive_rate")
+        .long("false-positive-rate")
+        .short("f")
+        .takes_value(true)
+        .default_value("0.05")
+        .help("fraction of the validation examples allowed to be flagged"))
+      .arg(Arg::with_name("labels")
+        .long("labels")
+        .short("l")
+        .takes_value(true)
+        .help("IDX label file, or text file with a label per line, for the scored examples"))
+      .arg(Arg::with_name("anomaly_classes")
+        .long("anomaly-classes")
+        .short("a")
+        .takes_value(true)
+        .multiple(true)
+        .number_of_values(1)
+        .use_delimiter(true)
+        .requires("labels")
+        .help("labels marking anomalies, comma-separated or repeated; every nonzero label by default"))
+      .arg(Arg::with_name("output")
+        .long("output")
+        .short("o")
+        .takes_value(true)
+        .default_value("scores.csv")
+        .help("CSV file with the score and flag of every example"))
+      .about("flag examples an autoencoder reconstructs badly, at a threshold calibrated on clean data"))
+    .subcommand(SubCommand::with_name("compare-pca")
+      .arg(Arg::with_name("model")
+        .long("model")
+        .short("m")
+        .takes_value(true)
+        .default_value("Model.bc")
+        .help("autoencoder compared with PCA"))
+      .arg(Arg::with_name("components")
+        .long("components")
+        .short("k")
+        .takes_value(true)
+        .help("number of principal components, the size of the model's bottleneck by default"))
+      .arg(Arg::with_name("limit")
+        .long("limit")
+        .short("n")
+        .takes_value(true)
+        .help("if defined, PCA is fitted to the first given number of training images only"))
+      .arg(Arg::with_name("data_dir")
+        .long("data-dir")
+        .short("d")
+        .takes_value(true)
+        .default_value("./test_data/")
+        .help("path to directory with test data"))
+      .arg(Arg::with_name("gamma")
+        .long("gamma")
+        .short("g")
+        .takes_value(true)
+        .default_value("1.0")
+        .help("gamma value when saving images"))
+      .arg(Arg::with_name("dir")
+        .long("dir")
+        .takes_value(true)
+        .default_value("./components/")
+        .help("principal components dump directory"))
+      .about("compare the test reconstruction error of an autoencoder with PCA of the same size"))
+    .subcommand(SubCommand::with_name("embed")
+      .arg(Arg::with_name("model")
+        .long("model")
+        .short("m")
+        .takes_value(true)
+        .default_value("Model.bc")
+        .help("model whose codes are embedded"))
+      .arg(Arg::with_name("layer")
+        .long("layer")
+        .short("l")
+        .takes_value(true)
+        .required(true)
+        .help("layer whose output is embedded, numbered as in `inspect`'s layer sizes"))
+      .arg(Arg::with_name("method")
+        .long("method")
+        .takes_value(true)
+        .possible_values(&["pca", "tsne"])
+        .default_value("tsne")
+        .help("dimensionality reduction method"))
+      .arg(Arg::with_name("amount")
+        .long("amount")
+        .short("n")
+        .takes_value(true)
+        .default_value("2000")
+        .help("number of test images embedded"))
+      .arg(Arg::with_name("perplexity")
+        .long("perplexity")
+        .takes_value(true)
+        .default_value("30")
+        .help("t-SNE perplexity"))
+      .arg(Arg::with_name("iterations")
+        .long("iterations")
+        .takes_value(true)
+        .default_value("1000")
+        .help("t-SNE iterations"))
+      .arg(Arg::with_name("data_dir")
+        .long("data-dir")
+        .short("d")
+        .takes_value(true)
+        .default_value("./test_data/")
+        .help("path to directory with test data"))
+      .arg(Arg::with_name("csv")
+        .long("csv")
+        .takes_value(true)
+        .default_value("embedding.csv")
+        .help("output file for the coordinates and labels"))
+      .arg(Arg::with_name("plot")
+        .long("plot")
+        .takes_value(true)
+        .default_value("embedding.png")
+        .help("output file for the scatter plot"))
+      .arg(Arg::with_name("size")
+        .long("size")
+        .takes_value(true)
+        .default_value("800")
+        .validator(|size| match size.parse::<usize>() {
+          Ok(size) if size >= 20 => Ok(()),
+          _ => Err(String::from("the scatter plot must be at least 20 pixels wide")),
+        })
+        .help("width and height of the scatter plot"))
+      .about("reduce the codes of a layer to 2-D and plot them coloured by digit"))
+    .subcommand(SubCommand::with_name("interpolate")
+      .arg(Arg::with_name("model")
+        .long("model")
+        .short("m")
+        .takes_value(true)
+        .default_value("Model.bc")
+        .help("autoencoder whose latent space is explored"))
+      .arg(Arg::with_name("layer")
+        .long("layer")
+        .short("l")
+        .takes_value(true)
+        .help("code layer, numbered as in `inspect`'s layer sizes; the smallest layer by default"))
+      .arg(Arg::with_name("from")
+        .long("from")
+        .takes_value(true)
+        .default_value("0")
+        .help("index of the test image the interpolation starts at"))
+      .arg(Arg::with_name("to")
+        .long("to")
+        .takes_value(true)
+        .default_value("1")
+        .help("index of the test image the interpolation ends at"))
+      .arg(Arg::with_name("steps")
+        .long("steps")
+        .short("s")
+        .takes_value(true)
+        .default_value("10")
+        .help("number of decoded codes, both ends included"))
+      .arg(Arg::with_name("spherical")
+        .long("spherical")
+        .help("interpolate along the arc between the codes instead of the line"))
+      .arg(Arg::with_name("data_dir")
+        .long("data-dir")
+        .short("d")
+        .takes_value(true)
+        .default_value("./test_data/")
+        .help("path to directory with test data"))
+      .arg(Arg::with_name("output")
+        .long("output")
+        .short("o")
+        .takes_value(true)
+        .default_value("interpolation.png")
+        .help("output file for the strip, framed by the two input images"))
+      .about("decode codes interpolated between the codes of two test images"))
+    .subcommand(SubCommand::with_name("traverse")
+      .arg(Arg::with_name("model")
+        .long("model")
+        .short("m")
+        .takes_value(true)
+        .default_value("Model.bc")
+        .help("autoencoder whose latent space is explored"))
+      .arg(Arg::with_name("layer")
+        .long("layer")
+        .short("l")
+        .takes_value(true)
+        .help("code layer, numbered as in `inspect`'s layer sizes; the smallest layer by default"))
+      .arg(Arg::with_name("image")
+        .long("image")
+        .short("i")
+        .takes_value(true)
+        .default_value("0")
+        .help("index of the test image whose code is varied"))
+      .arg(Arg::with_name("steps")
+        .long("steps")
+        .short("s")
+        .takes_value(true)
+        .default_value("10")
+        .help("number of values every unit is swept through"))
+      .arg(Arg::with_name("data_dir")
+        .long("data-dir")
+        .short("d")
+        .takes_value(true)
+        .default_value("./test_data/")
+        .help("path to directory with test data"))
+      .arg(Arg::with_name("output")
+        .long("output")
+        .short("o")
+        .takes_value(true)
+        .default_value("traversal.png")
+        .help("output file for the grid, one row per code unit"))
+      .about("sweep every code unit over the range seen on the test set and decode the results"))
     .subcommand(SubCommand::with_name("dump-features")
       .arg(Arg::with_name("model")
         .long("model")
@@ -67,6 +445,209 @@ pub fn get<'a>() -> ArgMatches<'a> {
         .takes_value(true)
         .default_value("./features/")
         .help("feature dump directory")))
+    .subcommand(SubCommand::with_name("visualize")
+      .arg(Arg::with_name("model")
+        .long("model")
+        .short("m")
+        .takes_value(true)
+        .default_value("Model.bc")
+        .help("model whose units are to be visualized"))
+      .arg(Arg::with_name("layer")
+        .long("layer")
+        .short("l")
+        .takes_value(true)
+        .required(true)
+        .help("layer whose units are visualized, numbered as in `inspect`'s layer sizes (2 is the first hidden layer)"))
+      .arg(Arg::with_name("units")
+        .long("units")
+        .short("u")
+        .takes_value(true)
+        .multiple(true)
+        .help("units to visualize; all units of the layer by default"))
+      .arg(Arg::with_name("norm")
+        .long("norm")
+        .short("n")
+        .takes_value(true)
+        .default_value("10.0")
+        .help("L2 norm bound of the generated inputs"))
+      .arg(Arg::with_name("step_size")
+        .long("step-size")
+        .takes_value(true)
+        .default_value("1.0")
+        .help("length of every gradient ascent step"))
+      .arg(Arg::with_name("iterations")
+        .long("iterations")
+        .short("i")
+        .takes_value(true)
+        .default_value("200")
+        .help("number of gradient ascent steps"))
+      .arg(Arg::with_name("gamma")
+        .long("gamma")
+        .short("g")
+        .takes_value(true)
+        .default_value("1.0")
+        .help("gamma value when saving images"))
+      .arg(Arg::with_name("dir")
+        .long("dir")
+        .short("d")
+        .takes_value(true)
+        .default_value("./features/")
+        .help("feature dump directory; images are named like those of `dump-features`, by the index of the layer producing the units"))
+      .about("generate the inputs that maximally activate units of any layer"))
+    .subcommand(SubCommand::with_name("saliency")
+      .arg(Arg::with_name("model")
+        .long("model")
+        .short("m")
+        .takes_value(true)
+        .default_value("Model.bc")
+        .help("classifier whose decisions are explained"))
+      .arg(Arg::with_name("images")
+        .long("images")
+        .short("i")
+        .takes_value(true)
+        .multiple(true)
+        .default_value("0")
+        .help("indices of the test images to explain"))
+      .arg(Arg::with_name("class")
+        .long("class")
+        .short("c")
+        .takes_value(true)
+        .help("output unit whose gradient is shown; the predicted class by default"))
+      .arg(Arg::with_name("data_dir")
+        .long("data-dir")
+        .short("d")
+        .takes_value(true)
+        .default_value("./test_data/")
+        .help("path to directory with test data"))
+      .arg(Arg::with_name("dir")
+        .long("dir")
+        .takes_value(true)
+        .default_value("./saliency/")
+        .help("output directory for the images, each showing the input next to its heatmap"))
+      .about("render how strongly every pixel of test images affects a classifier output"))
+    .subcommand(SubCommand::with_name("attack")
+      .arg(Arg::with_name("model")
+        .long("model")
+        .short("m")
+        .takes_value(true)
+        .default_value("Model.bc")
+        .help("classifier to attack"))
+      .arg(Arg::with_name("method")
+        .long("method")
+        .takes_value(true)
+        .possible_values(&["fgsm", "pgd"])
+        .default_value("pgd")
+        .help("fast gradient sign method or projected gradient descent"))
+      .arg(Arg::with_name("epsilon")
+        .long("epsilon")
+        .short("e")
+        .takes_value(true)
+        .default_value("0.1")
+        .help("largest change of a pixel, on the [0, 1] scale"))
+      .arg(Arg::with_name("steps")
+        .long("steps")
+        .short("s")
+        .takes_value(true)
+        .default_value("10")
+        .help("number of PGD steps"))
+      .arg(Arg::with_name("step_size")
+        .long("step-size")
+        .takes_value(true)
+        .help("size of every PGD step; a quarter of epsilon by default"))
+      .arg(Arg::with_name("limit")
+        .long("limit")
+        .short("n")
+        .takes_value(true)
+        .help("number of test images attacked; all by default"))
+      .arg(Arg::with_name("save")
+        .long("save")
+        .takes_value(true)
+        .default_value("10")
+        .help("number of successful adversarial examples saved"))
+      .arg(Arg::with_name("data_dir")
+        .long("data-dir")
+        .short("d")
+        .takes_value(true)
+        .default_value("./test_data/")
+        .help("path to directory with test data"))
+      .arg(Arg::with_name("dir")
+        .long("dir")
+        .takes_value(true)
+        .default_value("./adversarial/")
+        .help("output directory for the images, each showing the original next to its adversarial example"))
+      .about("measure the accuracy of a classifier on adversarially perturbed test images"))
+    .subcommand(SubCommand::with_name("inpaint")
+      .arg(Arg::with_name("model")
+        .long("model")
+        .short("m")
+        .takes_value(true)
+        .default_value("Model.bc")
+        .help("autoencoder filling in the occluded pixels"))
+      .arg(Arg::with_name("image")
+        .long("image")
+        .short("i")
+        .takes_value(true)
+        .default_value("0")
+        .help("index of the test image to occlude"))
+      .arg(Arg::with_name("mask")
+        .long("mask")
+        .takes_value(true)
+        .help("grayscale PNG of the image's size whose white pixels are occluded"))
+      .arg(Arg::with_name("square")
+        .long("square")
+        .takes_value(true)
+        .help("side of a randomly placed occluding square; half the image side by default"))
+      .group(ArgGroup::with_name("occlusion")
+        .args(&["mask", "square"]))
+      .arg(Arg::with_name("iterations")
+        .long("iterations")
+        .short("n")
+        .takes_value(true)
+        .default_value("50")
+        .help("largest number of autoencoder passes"))
+      .arg(Arg::with_name("tolerance")
+        .long("tolerance")
+        .short("t")
+        .takes_value(true)
+        .default_value("0.001")
+        .help("largest change of an occluded pixel at which the passes stop"))
+      .arg(Arg::with_name("data_dir")
+        .long("data-dir")
+        .short("d")
+        .takes_value(true)
+        .default_value("./test_data/")
+        .help("path to directory with test data"))
+      .arg(Arg::with_name("dir")
+        .long("dir")
+        .takes_value(true)
+        .default_value("./inpaint/")
+        .help("output directory for the images of every pass"))
+      .about("reconstruct occluded pixels of a test image by iterating an autoencoder"))
+    .subcommand(SubCommand::with_name("denoise")
+      .arg(Arg::with_name("model")
+        .long("model")
+        .short("m")
+        .takes_value(true)
+        .default_value("Model.bc")
+        .help("autoencoder cleaning the images"))
+      .arg(Arg::with_name("images")
+        .takes_value(true)
+        .multiple(true)
+        .required(true)
+        .value_name("IMAGE")
+        .help("image files (PNG, JPEG, PGM, ...) of single digits"))
+      .arg(Arg::with_name("noise")
+        .long("noise")
+        .short("n")
+        .takes_value(true)
+        .help("standard deviation of gaussian noise added to the images before denoising"))
+      .arg(Arg::with_name("dir")
+        .long("dir")
+        .short("d")
+        .takes_value(true)
+        .default_value("./denoised/")
+        .help("output directory; every image shows the prepared input, the noisy input if any, and the result"))
+      .about("run image files through an autoencoder after preparing them like MNIST digits"))
     .subcommand(SubCommand::with_name("sample")
       .arg(Arg::with_name("model")
         .long("model")
@@ -92,5 +673,82 @@ pub fn get<'a>() -> ArgMatches<'a> {
         .takes_value(true)
         .default_value("./sample/")
         .help("evaluated samples dump directory")))
+    .subcommand(SubCommand::with_name("inspect")
+      .arg(Arg::with_name("model")
+        .long("model")
+        .short("m")
+        .takes_value(true)
+        .default_value("Model.bc")
+        .help("model to be described"))
+      .about("print the layers of a model and their learned activation coefficients"))
+    .subcommand(SubCommand::with_name("model")
+      .subcommand(SubCommand::with_name("edit")
+        .arg(Arg::with_name("model")
+          .long("model")
+          .short("m")
+          .takes_value(true)
+          .default_value("Model.bc")
+          .help("model to be edited"))
+        .arg(Arg::with_name("output")
+          .long("output")
+          .short("o")
+          .takes_value(true)
+          .default_value("Model.bc")
+          .help("output file for the edited model"))
+        .arg(Arg::with_name("add_units")
+          .long("add-units")
+          .takes_value(true)
+          .value_name("LAYER:COUNT")
+          .help("add units with small random weights to a hidden layer, numbered as in `inspect`'s layer sizes (2 is the first hidden layer)"))
+        .arg(Arg::with_name("prune")
+          .long("prune")
+          .takes_value(true)
+          .value_name("LAYER:MIN_NORM")
+          .help("remove the units of a hidden layer whose outgoing weights have a smaller norm, numbered as in `inspect`'s layer sizes"))
+        .arg(Arg::with_name("insert_identity")
+          .long("insert-identity")
+          .takes_value(true)
+          .value_name("LAYER")
+          .help("insert a layer computing the identity after given layer, numbered as in `inspect`'s layer sizes (1 is the input)"))
+        .arg(Arg::with_name("append")
+          .long("append")
+          .takes_value(true)
+          .value_name("MODEL")
+          .help("stack given model on top of the edited one"))
+        .group(ArgGroup::with_name("operation")
+          .args(&["add_units", "prune", "insert_identity", "append"])
+          .required(true))
+        .about("change the topology of a trained model, keeping its weights"))
+      .about("model manipulation"))
+    .subcommand(SubCommand::with_name("som")
+      .arg(Arg::with_name("config")
+        .long("config")
+        .short("c")
+        .takes_value(true)
+        .default_value("Som.json")
+        .help("self-organizing map configuration file"))
+      .arg(Arg::with_name("model")
+        .long("model")
+        .short("m")
+        .takes_value(true)
+        .help("if defined, retrains given map"))
+      .arg(Arg::with_name("output")
+        .long("output")
+        .short("o")
+        .takes_value(true)
+        .default_value("Som.bc")
+        .help("output file for the map"))
+      .arg(Arg::with_name("codebook")
+        .long("codebook")
+        .takes_value(true)
+        .default_value("codebook.png")
+        .help("output file for the codebook montage"))
+      .arg(Arg::with_name("gamma")
+        .long("gamma")
+        .short("g")
+        .takes_value(true)
+        .default_value("1.0")
+        .help("gamma value when saving images"))
+      .about("train a self-organizing map"))
     .get_matches()
 }
@@ -0,0 +1,269 @@
+use na::{DMatrix, DVector};
+
+use layer::Layer;
+use nn::ActivationFunction;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum Cell {
+  Elman,
+  Gru,
+}
+
+/// Weights of one affine map `input * input_weights + prev * recurrent_weights + biases`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Gate {
+  pub input_weights: DMatrix<f32>,
+  pub recurrent_weights: DMatrix<f32>,
+  pub biases: DVector<f32>,
+}
+
+impl Gate {
+  fn new(step_size: usize, hidden_size: usize) -> Gate {
+    Gate {
+      input_weights: DMatrix::new_zeros(step_size, hidden_size),
+      recurrent_weights: DMatrix::new_zeros(hidden_size, hidden_size),
+      biases: DVector::new_zeros(hidden_size),
+    }
+  }
+
+  fn net(&self, input: &DVector<f32>, prev: &DVector<f32>) -> DVector<f32> {
+    let mut from_input = input.clone();
+    from_input *= &self.input_weights;
+    let mut from_prev = prev.clone();
+    from_prev *= &self.recurrent_weights;
+    (0..self.biases.len()).map(|u| from_input[u] + from_prev[u] + self.biases[u]).collect()
+  }
+
+  fn accumulate(&mut self, input: &DVector<f32>, prev: &DVector<f32>, delta: &DVector<f32>) {
+    use na::Outer;
+
+    self.input_weights += input.outer(delta);
+    self.recurrent_weights += prev.outer(delta);
+    self.biases += delta.clone();
+  }
+
+  fn sum(mut a: Vec<Gate>, b: Vec<Gate>) -> Vec<Gate> {
+    for (a, b) in a.iter_mut().zip(b) {
+      a.input_weights += b.input_weights;
+      a.recurrent_weights += b.recurrent_weights;
+      a.biases += b.biases;
+    }
+    a
+  }
+}
+
+/// Reads its input as `steps` consecutive, equally sized vectors (e.g. the rows of an image) and
+/// outputs the hidden state after the last one. An Elman cell uses the network's activation
+/// function; a GRU cell always uses sigmoid gates and a tanh candidate state.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Recurrent {
+  pub cell: Cell,
+  /// Elman: a single gate. GRU: the update gate, the reset gate and the candidate state.
+  pub gates: Vec<Gate>,
+  pub steps: usize,
+  pub activation_fn: ActivationFunction,
+  pub activation_coeff: f32,
+}
+
+struct Step {
+  input: DVector<f32>,
+  prev: DVector<f32>,
+  /// Elman: the pre-activation. GRU: the update gate, reset gate and candidate state.
+  gates: Vec<DVector<f32>>,
+}
+
+pub struct RecurrentCache {
+  steps: Vec<Vec<Step>>,
+}
+
+impl Recurrent {
+  pub fn new(cell: Cell, inputs: usize, hidden_size: usize, steps: usize, activation_fn: ActivationFunction, activation_coeff: f32) -> Recurrent {
+    let gates = match cell {
+      Cell::Elman => 1,
+      Cell::Gru => 3,
+    };
+    Recurrent {
+      cell: cell,
+      gates: (0..gates).map(|_| Gate::new(inputs / steps, hidden_size)).collect(),
+      steps: steps,
+      activation_fn: activation_fn,
+      activation_coeff: activation_coeff,
+    }
+  }
+
+  pub fn step_size(&self) -> usize {
+    self.gates[0].input_weights.nrows()
+  }
+
+  pub fn hidden_size(&self) -> usize {
+    self.gates[0].biases.len()
+  }
+
+  fn run(&self, input: &DVector<f32>) -> (DVector<f32>, Vec<Step>) {
+    let step_size = self.step_size();
+    let size = self.hidden_size();
+    let sigmoid = ActivationFunction::Sigmoid;
+
+    let mut h = DVector::new_zeros(size);
+    let mut steps = Vec::with_capacity(self.steps);
+    for t in 0..self.steps {
+      let x = DVector { at: input.at[(t * step_size)..((t + 1) * step_size)].to_vec() };
+      let (next, gates) = match self.cell {
+        Cell::Elman => {
+          let a = self.gates[0].net(&x, &h);
+          let next = (0..size).map(|u| self.activation_fn.function(a[u], self.activation_coeff)).collect();
+          (next, vec![a])
+        },
+        Cell::Gru => {
+          let z = self.gates[0].net(&x, &h).at.into_iter().map(|a| sigmoid.function(a, 1.0)).collect::<DVector<f32>>();
+          let r = self.gates[1].net(&x, &h).at.into_iter().map(|a| sigmoid.function(a, 1.0)).collect::<DVector<f32>>();
+          let reset = (0..size).map(|u| r[u] * h[u]).collect();
+          let candidate = self.gates[2].net(&x, &reset).at.into_iter().map(|a| a.tanh()).collect::<DVector<f32>>();
+          let next = (0..size).map(|u| (1.0 - z[u]) * candidate[u] + z[u] * h[u]).collect();
+          (next, vec![z, r, candidate])
+        },
+      };
+      steps.push(Step { input: x, prev: h, gates: gates });
+      h = next;
+    }
+
+    (h, steps)
+  }
+
+  fn zero_gates(&self) -> Vec<Gate> {
+    self.gates.iter().map(|_| Gate::new(self.step_size(), self.hidden_size())).collect()
+  }
+
+  /// Backpropagation through time for a single example, accumulating the parameter gradients
+  /// into `updates` if given.
+  fn backpropagate(&self, steps: &[Step], delta: &DVector<f32>, mut updates: Option<&mut Vec<Gate>>) -> DVector<f32> {
+    let step_size = self.step_size();
+    let size = self.hidden_size();
+
+    let mut input_delta = vec![0.0; self.steps * step_size];
+    let mut dh = delta.clone();
+
+    for (t, step) in steps.iter().enumerate().rev() {
+      let (dx, dprev): (DVector<f32>, DVector<f32>) = match self.cell {
+        Cell::Elman => {
+          let da: DVector<f32> = (0..size).map(|u| dh[u] * self.activation_fn.derivative(step.gates[0][u], self.activation_coeff)).collect();
+          if let Some(ref mut updates) = updates {
+            updates[0].accumulate(&step.input, &step.prev, &da);
+          }
+          (&self.gates[0].input_weights * &da, &self.gates[0].recurrent_weights * &da)
+        },
+        Cell::Gru => {
+          let (z, r, candidate, h) = (&step.gates[0], &step.gates[1], &step.gates[2], &step.prev);
+          let reset: DVector<f32> = (0..size).map(|u| r[u] * h[u]).collect();
+
+          let d_candidate: DVector<f32> = (0..size).map(|u| dh[u] * (1.0 - z[u]) * (1.0 - candidate[u] * candidate[u])).collect();
+          let d_z: DVector<f32> = (0..size).map(|u| dh[u] * (h[u] - candidate[u]) * z[u] * (1.0 - z[u])).collect();
+          let d_reset = &self.gates[2].recurrent_weights * &d_candidate;
+          let d_r: DVector<f32> = (0..size).map(|u| d_reset[u] * h[u] * r[u] * (1.0 - r[u])).collect();
+
+          if let Some(ref mut updates) = updates {
+            updates[0].accumulate(&step.input, h, &d_z);
+            updates[1].accumulate(&step.input, h, &d_r);
+            updates[2].accumulate(&step.input, &reset, &d_candidate);
+          }
+
+          let via_z = &self.gates[0].recurrent_weights * &d_z;
+          let via_r = &self.gates[1].recurrent_weights * &d_r;
+          let dprev = (0..size).map(|u| dh[u] * z[u] + d_reset[u] * r[u] + via_z[u] + via_r[u]).collect();
+
+          let dx_z = &self.gates[0].input_weights * &d_z;
+          let dx_r = &self.gates[1].input_weights * &d_r;
+          let dx_candidate = &self.gates[2].input_weights * &d_candidate;
+          let dx = (0..step_size).map(|i| dx_z[i] + dx_r[i] + dx_candidate[i]).collect();
+          (dx, dprev)
+        },
+      };
+
+      input_delta[(t * step_size)..((t + 1) * step_size)].copy_from_slice(&dx.at);
+      dh = dprev;
+    }
+
+    DVector { at: input_delta }
+  }
+}
+
+impl Layer for Recurrent {
+  type Cache = RecurrentCache;
+
+  fn input_size(&self) -> usize {
+    self.step_size() * self.steps
+  }
+
+  fn output_size(&self) -> usize {
+    self.hidden_size()
+  }
+
+  fn forward(&self, inputs: &[DVector<f32>], _train: bool) -> (Vec<DVector<f32>>, RecurrentCache) {
+    use rayon::prelude::*;
+
+    let (outputs, steps) = inputs.par_iter()
+      .map(|input| self.run(input))
+      .collect::<Vec<_>>()
+      .into_iter()
+      .unzip();
+
+    (outputs, RecurrentCache { steps: steps })
+  }
+
+  fn backward(&self, cache: &RecurrentCache, output_deltas: &[DVector<f32>]) -> (Vec<DVector<f32>>, Vec<Vec<f32>>) {
+    use rayon::prelude::*;
+
+    let (input_deltas, updates): (Vec<_>, Vec<_>) = cache.steps.par_iter().zip(output_deltas.par_iter())
+      .map(|(steps, delta)| {
+        let mut update = self.zero_gates();
+        let input_delta = self.backpropagate(steps, delta, Some(&mut update));
+        (input_delta, update)
+      })
+      .collect::<Vec<_>>()
+      .into_iter()
+      .unzip();
+    let update = updates.into_iter().fold(self.zero_gates(), Gate::sum);
+
+    (input_deltas, update.into_iter().flat_map(|g| vec![g.input_weights.as_vector().to_vec(), g.recurrent_weights.as_vector().to_vec(), g.biases.at]).collect())
+  }
+
+  fn backward_inputs(&self, cache: &RecurrentCache, output_deltas: &[DVector<f32>]) -> Vec<DVector<f32>> {
+    use rayon::prelude::*;
+
+    cache.steps.par_iter().zip(output_deltas.par_iter())
+      .map(|(steps, delta)| self.backpropagate(steps, delta, None))
+      .collect()
+  }
+
+  fn params(&self) -> Vec<&[f32]> {
+    self.gates.iter().flat_map(|g| vec![g.input_weights.as_vector(), g.recurrent_weights.as_vector(), &g.biases.at[..]]).collect()
+  }
+
+  fn params_mut(&mut self) -> Vec<&mut [f32]> {
+    self.gates.iter_mut().flat_map(|g| vec![g.input_weights.as_mut_vector(), g.recurrent_weights.as_mut_vector(), &mut g.biases.at[..]]).collect()
+  }
+
+  fn decays(&self, idx: usize) -> bool {
+    idx % 3 != 2
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use gradient_check::{check, layers};
+  use nn::Network;
+
+  #[test]
+  fn recurrent_gradients() {
+    for cell in &["rnn", "gru"] {
+      let json = format!(r#"{{"layers": [6, 4, 3], "activation_coeffs": [1, 1], "activation_fn": "tanh", "layer_types": ["{}", "softmax"], "sequence_steps": 3}}"#, cell);
+      check(layers(&json).remove(0), 3);
+    }
+  }
+
+  #[test]
+  fn inputs_must_split_into_steps() {
+    let defn = ::sj::from_str(r#"{"layers": [7, 4], "activation_coeffs": [1], "activation_fn": "tanh", "layer_types": ["gru"], "sequence_steps": 3}"#).unwrap();
+    assert!(Network::from_definition(&defn).is_err());
+  }
+}
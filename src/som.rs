@@ -0,0 +1,275 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::{error, fmt};
+
+use rand::Rng;
+
+use na::DVector;
+
+use bc;
+
+#[derive(Serialize, Deserialize, Clone, Copy)]
+pub struct SomConfig {
+  pub width: usize,
+  pub height: usize,
+  pub epochs: usize,
+  pub initial_learning_rate: f32,
+  pub final_learning_rate: f32,
+  /// Width of the gaussian neighbourhood on the grid; defaults to half of the larger grid side.
+  pub initial_radius: Option<f32>,
+  pub final_radius: f32,
+  pub epoch_log_period: Option<usize>,
+}
+
+impl SomConfig {
+  /// Checks the settings `train` would divide by or decay towards.
+  pub fn validate(&self) -> Result<(), SomConfigError> {
+    for &(field, value) in &[("width", self.width), ("height", self.height), ("epochs", self.epochs), ("epoch_log_period", self.epoch_log_period.unwrap_or(1))] {
+      if value == 0 {
+        return Err(SomConfigError::Zero(field));
+      }
+    }
+    // the decay is exponential, so it can neither start nor end at zero
+    for &(field, value) in &[("initial_learning_rate", self.initial_learning_rate), ("final_learning_rate", self.final_learning_rate),
+        ("initial_radius", self.initial_radius.unwrap_or(1.0)), ("final_radius", self.final_radius)] {
+      if !(value > 0.0) {
+        return Err(SomConfigError::NotPositive(field));
+      }
+    }
+    Ok(())
+  }
+
+  /// Exponential decay from `initial` at the start of training to `last` at its end.
+  fn decay(initial: f32, last: f32, progress: f32) -> f32 {
+    initial * (last / initial).powf(progress)
+  }
+
+  pub fn learning_rate(&self, progress: f32) -> f32 {
+    SomConfig::decay(self.initial_learning_rate, self.final_learning_rate, progress)
+  }
+
+  pub fn radius(&self, progress: f32) -> f32 {
+    let initial = self.initial_radius.unwrap_or(self.width.max(self.height) as f32 / 2.0);
+    SomConfig::decay(initial, self.final_radius, progress)
+  }
+}
+
+#[derive(Debug)]
+pub enum SomConfigError {
+  Zero(&'static str),
+  NotPositive(&'static str),
+}
+
+impl fmt::Display for SomConfigError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      &SomConfigError::Zero(field) => write!(f, "`{}` must be at least 1", field),
+      &SomConfigError::NotPositive(field) => write!(f, "`{}` must be positive", field),
+    }
+  }
+}
+
+impl error::Error for SomConfigError {
+  fn description(&self) -> &str {
+    "invalid self-organizing map configuration"
+  }
+}
+
+/// A Kohonen self-organizing map: a rectangular grid of units, each holding a codebook vector in
+/// the input space. Unit `(x, y)` is `codebook[y * width + x]`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Som {
+  pub width: usize,
+  pub height: usize,
+  pub codebook: Vec<DVector<f32>>,
+}
+
+const SOM_MAGIC: &'static [u8] = b"FSOM";
+const SOM_VERSION: u32 = 1;
+
+impl Som {
+  pub fn new(width: usize, height: usize, input_size: usize) -> Som {
+    Som {
+      width: width,
+      height: height,
+      codebook: vec![DVector::new_zeros(input_size); width * height],
+    }
+  }
+
+  pub fn load(path: &str) -> bc::Result<Som> {
+    use std::fs::File;
+    use std::io::{Read, BufReader};
+
+    let mut file = BufReader::new(File::open(path)?);
+    let mut magic = [0; 4];
+    file.read_exact(&mut magic)?;
+    if magic != SOM_MAGIC {
+      return Err(bc::ErrorKind::Custom(format!("{} is not a self-organizing map", path)).into());
+    }
+    let version: u32 = bc::deserialize_from(&mut file, bc::Infinite)?;
+    if version != SOM_VERSION {
+      return Err(bc::ErrorKind::Custom(format!("unsupported map version {} in {}", version, path)).into());
+    }
+    bc::deserialize_from(&mut file, bc::Infinite)
+  }
+
+  pub fn save(&self, path: &str) -> bc::Result<()> {
+    use std::fs::File;
+    use std::io::{Write, BufWriter};
+
+    let mut file = BufWriter::new(File::create(path)?);
+    file.write_all(SOM_MAGIC)?;
+    bc::serialize_into(&mut file, &SOM_VERSION, bc::Infinite)?;
+    bc::serialize_into(&mut file, self, bc::Infinite)
+  }
+
+  pub fn input_size(&self) -> usize {
+    self.codebook[0].len()
+  }
+
+  /// Initialises every codebook vector with a randomly picked example.
+  pub fn assign_random_weights<R: Rng>(&mut self, rng: &mut R, data: &[Vec<f32>]) {
+    for unit in &mut self.codebook {
+      *unit = DVector { at: rng.choose(data).unwrap().clone() };
+    }
+  }
+
+  fn distance_squared(unit: &DVector<f32>, example: &[f32]) -> f32 {
+    unit.at.iter().zip(example).map(|(w, x)| (w - x) * (w - x)).sum()
+  }
+
+  fn grid_distance_squared(&self, a: usize, b: usize) -> f32 {
+    let (ax, ay) = ((a % self.width) as f32, (a / self.width) as f32);
+    let (bx, by) = ((b % self.width) as f32, (b / self.width) as f32);
+    (ax - bx) * (ax - bx) + (ay - by) * (ay - by)
+  }
+
+  /// Index of the unit closest to `example`.
+  pub fn best_matching_unit(&self, example: &[f32]) -> usize {
+    self.best_two(example).0
+  }
+
+  /// Indices of the closest and second closest units.
+  fn best_two(&self, example: &[f32]) -> (usize, usize) {
+    let distances = self.codebook.iter().map(|u| Som::distance_squared(u, example)).collect::<Vec<_>>();
+    let mut best = (0, 0);
+    let mut best_dist = (::std::f32::INFINITY, ::std::f32::INFINITY);
+    for (it, &d) in distances.iter().enumerate() {
+      if d < best_dist.0 {
+        best = (it, best.0);
+        best_dist = (d, best_dist.0);
+      } else if d < best_dist.1 {
+        best.1 = it;
+        best_dist.1 = d;
+      }
+    }
+    best
+  }
+
+  /// Mean distance between the examples and their best matching units.
+  pub fn quantization_error(&self, data: &[Vec<f32>]) -> f32 {
+    use rayon::prelude::*;
+
+    data.par_iter()
+      .map(|ex| Som::distance_squared(&self.codebook[self.best_matching_unit(ex)], ex).sqrt())
+      .sum::<f32>()
+      / data.len() as f32
+  }
+
+  /// Fraction of examples whose two closest units are not neighbours on the grid (including
+  /// diagonal neighbours).
+  pub fn topographic_error(&self, data: &[Vec<f32>]) -> f32 {
+    use rayon::prelude::*;
+
+    data.par_iter()
+      .filter(|ex| {
+        let (first, second) = self.best_two(ex);
+        self.grid_distance_squared(first, second) > 2.0
+      })
+      .count() as f32
+      / data.len() as f32
+  }
+
+  /// Online training: every example pulls its best matching unit and the unit's grid neighbours
+  /// towards itself, with learning rate and neighbourhood radius decaying over all epochs.
+  pub fn train<R: Rng>(&mut self, rng: &mut R, data: &[Vec<f32>], conf: &SomConfig, learning: Option<Arc<AtomicBool>>) {
+    let total_steps = (conf.epochs * data.len()) as f32;
+    let mut order = (0..data.len()).collect::<Vec<_>>();
+    let mut step = 0;
+
+    for epoch in 0..conf.epochs {
+      rng.shuffle(&mut order);
+      for &it in &order {
+        let progress = step as f32 / total_steps;
+        let learning_rate = conf.learning_rate(progress);
+        let radius = conf.radius(progress);
+        let example = &data[it];
+        let bmu = self.best_matching_unit(example);
+
+        for unit in 0..self.codebook.len() {
+          let influence = (-self.grid_distance_squared(bmu, unit) / (2.0 * radius * radius)).exp();
+          // units far away from the winner would barely move
+          if influence < 1e-3 {
+            continue;
+          }
+          for (w, x) in self.codebook[unit].at.iter_mut().zip(example) {
+            *w += learning_rate * influence * (x - *w);
+          }
+        }
+        step += 1;
+      }
+
+      if let Some(period) = conf.epoch_log_period {
+        if epoch % period == 0 {
+          println!("#{} - quantization err: {}, topographic err: {}", epoch, self.quantization_error(data), self.topographic_error(data));
+        }
+      }
+      if let Some(ref learning) = learning {
+        if !learning.load(Ordering::SeqCst) {
+          break;
+        }
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn config() -> SomConfig {
+    ::sj::from_str(r#"{"width": 3, "height": 1, "epochs": 1, "initial_learning_rate": 0.5,
+      "final_learning_rate": 0.01, "initial_radius": null, "final_radius": 0.5, "epoch_log_period": null}"#).unwrap()
+  }
+
+  #[test]
+  fn rejects_degenerate_configs() {
+    assert!(config().validate().is_ok());
+    match (SomConfig { final_radius: 0.0, ..config() }).validate() {
+      Err(SomConfigError::NotPositive("final_radius")) => {},
+      other => panic!("unexpected result {:?}", other),
+    }
+    match (SomConfig { initial_learning_rate: -0.1, ..config() }).validate() {
+      Err(SomConfigError::NotPositive("initial_learning_rate")) => {},
+      other => panic!("unexpected result {:?}", other),
+    }
+    match (SomConfig { epoch_log_period: Some(0), ..config() }).validate() {
+      Err(SomConfigError::Zero("epoch_log_period")) => {},
+      other => panic!("unexpected result {:?}", other),
+    }
+  }
+
+  #[test]
+  fn map_errors() {
+    // a 3 x 1 map whose codebook is folded: the middle unit holds the largest value
+    let som = Som {
+      width: 3,
+      height: 1,
+      codebook: vec![DVector { at: vec![0.0] }, DVector { at: vec![2.0] }, DVector { at: vec![1.0] }],
+    };
+    // 0.4 lies closest to units 0 and 2, which are not neighbours; the others are fine
+    let data = vec![vec![0.4], vec![1.9], vec![1.1]];
+    assert!((som.quantization_error(&data) - 0.2).abs() < 1e-6);
+    assert!((som.topographic_error(&data) - 1.0 / 3.0).abs() < 1e-6);
+  }
+}
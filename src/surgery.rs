@@ -0,0 +1,187 @@
+use std::{error, fmt};
+
+use rand::Rng;
+
+use na::{DMatrix, DVector};
+
+use layer::{Layer, LayerKind, Dense};
+use batch_norm::BatchNorm;
+use nn::{Sequential, Network, ActivationFunction};
+
+/// Topology changes of a trained network. Layers are numbered as in `eval_to_layer`, so layer
+/// `k` is `layer_sizes()[k - 1]`: the output of `layers[k - 2]` and the input of `layers[k - 1]`.
+/// Hidden layers are numbered from 2.
+impl Sequential {
+  /// Adds `count` units to a hidden layer, with small random incoming and outgoing weights.
+  pub fn add_units<R: Rng>(&mut self, layer: usize, count: usize, rng: &mut R) -> Result<(), EditError> {
+    use rand::distributions::{Normal, IndependentSample};
+
+    let dist = Normal::new(0.0, 0.01);
+    let (producer, consumer) = self.hidden_layer_mut(layer)?;
+    let keep = (0..producer.output_size()).collect::<Vec<_>>();
+    producer.remap_outputs(&keep, count, || dist.ind_sample(rng) as f32);
+    consumer.remap_inputs(&keep, count, || dist.ind_sample(rng) as f32);
+    Ok(())
+  }
+
+  /// Removes the units of a hidden layer whose outgoing weights have a norm below `min_norm`,
+  /// returning how many were removed.
+  pub fn prune_units(&mut self, layer: usize, min_norm: f32) -> Result<usize, EditError> {
+    let (producer, consumer) = self.hidden_layer_mut(layer)?;
+    let keep = (0..consumer.input_size())
+      .filter(|&u| (0..consumer.output_size()).map(|j| consumer.weights[(u, j)] * consumer.weights[(u, j)]).sum::<f32>().sqrt() >= min_norm)
+      .collect::<Vec<_>>();
+    if keep.is_empty() {
+      return Err(EditError::NoUnitsLeft(layer));
+    }
+
+    let removed = consumer.input_size() - keep.len();
+    producer.remap_outputs(&keep, 0, || 0.0);
+    consumer.remap_inputs(&keep, 0, || 0.0);
+    Ok(removed)
+  }
+
+  /// Inserts a linear layer computing the identity right after the given layer, which leaves
+  /// the network's output unchanged until it is trained.
+  pub fn insert_identity_layer(&mut self, layer: usize) -> Result<(), EditError> {
+    if layer < 1 || layer > self.layers.len() + 1 {
+      return Err(EditError::NoSuchLayer(layer));
+    }
+    let size = self.layer_sizes()[layer - 1];
+    let identity = Dense {
+      weights: DMatrix::from_fn(size, size, |i, j| if i == j { 1.0 } else { 0.0 }),
+      ..Dense::new(size, size, ActivationFunction::Identity, 1.0)
+    };
+    self.layers.insert(layer - 1, LayerKind::Dense(identity));
+    Ok(())
+  }
+
+  /// Stacks `other` on top of this network, feeding it this network's output.
+  pub fn concat(mut self, other: Network) -> Result<Network, EditError> {
+    if self.output_size() != other.input_size() {
+      return Err(EditError::SizeMismatch { output: self.output_size(), input: other.input_size() });
+    }
+    self.layers.extend(other.layers);
+    Ok(self)
+  }
+
+  fn hidden_layer_mut(&mut self, layer: usize) -> Result<(&mut Dense, &mut Dense), EditError> {
+    if layer < 2 || layer > self.layers.len() {
+      return Err(EditError::NoSuchLayer(layer));
+    }
+    let (below, above) = self.layers.split_at_mut(layer - 1);
+    match (dense_mut(&mut below[layer - 2]), dense_mut(&mut above[0])) {
+      (Some(producer), Some(consumer)) => Ok((producer, consumer)),
+      _ => Err(EditError::NotDense(layer)),
+    }
+  }
+}
+
+/// The affine part of layers that have one.
+fn dense_mut(layer: &mut LayerKind) -> Option<&mut Dense> {
+  match layer {
+    &mut LayerKind::Dense(ref mut dense) => Some(dense),
+    &mut LayerKind::Softmax(ref mut softmax) => Some(&mut softmax.linear),
+    _ => None,
+  }
+}
+
+impl Dense {
+  /// Keeps the output units listed in `keep`, in order, then appends `added` units whose weights
+  /// are drawn from `init`.
+  fn remap_outputs<F: FnMut() -> f32>(&mut self, keep: &[usize], added: usize, mut init: F) {
+    let outputs = keep.len() + added;
+    let weights = DMatrix::from_fn(self.input_size(), outputs, |i, j| if j < keep.len() { self.weights[(i, keep[j])] } else { init() });
+    self.weights = weights;
+    self.biases = remap(&self.biases, keep, added, 0.0);
+
+    if self.activation_coeffs.len() > 1 {
+      let coeff = self.activation_coeffs[0];
+      self.activation_coeffs = remap(&self.activation_coeffs, keep, added, coeff);
+    }
+    if let Some(ref mut bn) = self.batch_norm {
+      let fresh = BatchNorm::new(1);
+      bn.scale = remap(&bn.scale, keep, added, fresh.scale[0]);
+      bn.shift = remap(&bn.shift, keep, added, fresh.shift[0]);
+      bn.running_mean = remap(&bn.running_mean, keep, added, fresh.running_mean[0]);
+      bn.running_var = remap(&bn.running_var, keep, added, fresh.running_var[0]);
+    }
+  }
+
+  /// Keeps the input units listed in `keep`, in order, then appends `added` units whose weights
+  /// are drawn from `init`.
+  fn remap_inputs<F: FnMut() -> f32>(&mut self, keep: &[usize], added: usize, mut init: F) {
+    let inputs = keep.len() + added;
+    let weights = DMatrix::from_fn(inputs, self.output_size(), |i, j| if i < keep.len() { self.weights[(keep[i], j)] } else { init() });
+    self.weights = weights;
+  }
+}
+
+fn remap(values: &DVector<f32>, keep: &[usize], added: usize, default: f32) -> DVector<f32> {
+  keep.iter().map(|&u| values[u]).chain((0..added).map(|_| default)).collect()
+}
+
+#[derive(Debug)]
+pub enum EditError {
+  NoSuchLayer(usize),
+  NotDense(usize),
+  NoUnitsLeft(usize),
+  SizeMismatch { output: usize, input: usize },
+}
+
+impl fmt::Display for EditError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      &EditError::NoSuchLayer(layer) => write!(f, "layer {} is not a hidden layer of the network", layer),
+      &EditError::NotDense(layer) => write!(f, "layer {} is not between two dense layers", layer),
+      &EditError::NoUnitsLeft(layer) => write!(f, "pruning would remove every unit of layer {}", layer),
+      &EditError::SizeMismatch { output, input } => write!(f, "cannot feed an output of size {} to a network taking {} inputs", output, input),
+    }
+  }
+}
+
+impl error::Error for EditError {
+  fn description(&self) -> &str {
+    "invalid network edit"
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use rand::{SeedableRng, XorShiftRng};
+
+  use nn::Network;
+
+  fn network() -> Network {
+    let defn = ::sj::from_str(r#"{"layers": [4, 5, 3], "activation_coeffs": [1, 1], "activation_fn": "sigmoid"}"#).unwrap();
+    let mut net = Network::from_definition(&defn).unwrap();
+    net.assign_random_weights(&mut XorShiftRng::from_seed([1, 2, 3, 4]));
+    net
+  }
+
+  #[test]
+  fn layers_are_numbered_as_in_eval_to_layer() {
+    let mut net = network();
+    net.add_units(2, 2, &mut XorShiftRng::from_seed([5, 6, 7, 8])).unwrap();
+    assert_eq!(net.layer_sizes(), vec![4, 7, 3]);
+    assert_eq!(net.eval_to_layer(vec![0.1, 0.5, 0.3, 0.9], 2).len(), net.layer_sizes()[2 - 1]);
+
+    assert!(net.add_units(1, 1, &mut XorShiftRng::from_seed([5, 6, 7, 8])).is_err());
+    assert!(net.add_units(3, 1, &mut XorShiftRng::from_seed([5, 6, 7, 8])).is_err());
+  }
+
+  #[test]
+  fn identity_layer_keeps_outputs() {
+    let x = vec![0.1, 0.5, 0.3, 0.9];
+    for layer in 1..4 {
+      let mut net = network();
+      let expected = net.eval(x.clone());
+      net.insert_identity_layer(layer).unwrap();
+      assert_eq!(net.layer_sizes().len(), 4);
+      assert_eq!(net.layer_sizes()[layer - 1], net.layer_sizes()[layer]);
+      for (e, y) in expected.iter().zip(net.eval(x.clone())) {
+        assert!((e - y).abs() < 1e-6);
+      }
+    }
+  }
+}
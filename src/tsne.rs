@@ -0,0 +1,123 @@
+use rand::Rng;
+
+#[derive(Clone, Copy, Debug)]
+pub struct TsneConfig {
+  pub perplexity: f32,
+  pub iterations: usize,
+  pub learning_rate: f32,
+  /// Factor the input affinities are multiplied by during the first `exaggeration_iterations`.
+  pub early_exaggeration: f32,
+  pub exaggeration_iterations: usize,
+}
+
+impl Default for TsneConfig {
+  fn default() -> TsneConfig {
+    TsneConfig {
+      perplexity: 30.0,
+      iterations: 1000,
+      learning_rate: 200.0,
+      early_exaggeration: 12.0,
+      exaggeration_iterations: 250,
+    }
+  }
+}
+
+/// Exact t-SNE embedding of `data` into the plane; every iteration costs `O(n^2)`.
+pub fn tsne<R: Rng>(rng: &mut R, data: &[Vec<f32>], conf: &TsneConfig) -> Vec<[f32; 2]> {
+  use rand::distributions::{Normal, IndependentSample};
+  use rayon::prelude::*;
+
+  let n = data.len();
+  let p = joint_affinities(data, conf.perplexity as f64);
+
+  let dist = Normal::new(0.0, 1e-4);
+  let mut y = (0..n).map(|_| [dist.ind_sample(rng), dist.ind_sample(rng)]).collect::<Vec<[f64; 2]>>();
+  let mut velocity = vec![[0.0; 2]; n];
+  let mut gains = vec![[1.0; 2]; n];
+
+  for iteration in 0..conf.iterations {
+    let exaggeration = if iteration < conf.exaggeration_iterations { conf.early_exaggeration as f64 } else { 1.0 };
+    let momentum = if iteration < conf.exaggeration_iterations { 0.5 } else { 0.8 };
+
+    // student-t kernel between every pair of embedded points
+    let kernel = y.par_iter()
+      .map(|yi| y.iter().map(|yj| 1.0 / (1.0 + (yi[0] - yj[0]).powi(2) + (yi[1] - yj[1]).powi(2))).collect::<Vec<_>>())
+      .collect::<Vec<_>>();
+    let kernel_sum = kernel.iter().enumerate().map(|(i, row)| row.iter().sum::<f64>() - row[i]).sum::<f64>();
+
+    let gradient = (0..n).into_par_iter()
+      .map(|i| {
+        let mut g = [0.0; 2];
+        for j in (0..n).filter(|&j| j != i) {
+          let force = (exaggeration * p[i][j] - kernel[i][j] / kernel_sum) * kernel[i][j];
+          g[0] += 4.0 * force * (y[i][0] - y[j][0]);
+          g[1] += 4.0 * force * (y[i][1] - y[j][1]);
+        }
+        g
+      })
+      .collect::<Vec<_>>();
+
+    for i in 0..n {
+      for d in 0..2 {
+        // grow the step where the gradient keeps its direction
+        gains[i][d] = if (gradient[i][d] > 0.0) != (velocity[i][d] > 0.0) { gains[i][d] + 0.2 } else { (gains[i][d] * 0.8f64).max(0.01) };
+        velocity[i][d] = momentum * velocity[i][d] - conf.learning_rate as f64 * gains[i][d] * gradient[i][d];
+        y[i][d] += velocity[i][d];
+      }
+    }
+
+    let mean = [y.iter().map(|p| p[0]).sum::<f64>() / n as f64, y.iter().map(|p| p[1]).sum::<f64>() / n as f64];
+    for p in &mut y {
+      p[0] -= mean[0];
+      p[1] -= mean[1];
+    }
+  }
+
+  y.into_iter().map(|p| [p[0] as f32, p[1] as f32]).collect()
+}
+
+/// Symmetrised affinities `p_ij`, with the gaussian around every point sized by binary search to
+/// match the perplexity.
+fn joint_affinities(data: &[Vec<f32>], perplexity: f64) -> Vec<Vec<f64>> {
+  use rayon::prelude::*;
+
+  let n = data.len();
+  let target_entropy = perplexity.ln();
+
+  let conditional = data.par_iter().enumerate()
+    .map(|(i, xi)| {
+      let distances = data.iter()
+        .map(|xj| xi.iter().zip(xj).map(|(a, b)| ((a - b) * (a - b)) as f64).sum::<f64>())
+        .collect::<Vec<_>>();
+
+      let (mut beta, mut low, mut high) = (1.0, 0.0, ::std::f64::INFINITY);
+      let mut row = vec![0.0; n];
+      for _ in 0..50 {
+        for (j, p) in row.iter_mut().enumerate() {
+          *p = if j == i { 0.0 } else { (-beta * distances[j]).exp() };
+        }
+        let sum = row.iter().sum::<f64>().max(1e-300);
+        let entropy = sum.ln() + beta * row.iter().zip(&distances).map(|(p, d)| p * d).sum::<f64>() / sum;
+        for p in row.iter_mut() {
+          *p /= sum;
+        }
+
+        if (entropy - target_entropy).abs() < 1e-5 {
+          break;
+        }
+        if entropy > target_entropy {
+          low = beta;
+          beta = if high.is_infinite() { beta * 2.0 } else { (beta + high) / 2.0 };
+        } else {
+          high = beta;
+          beta = (beta + low) / 2.0;
+        }
+      }
+      row
+    })
+    .collect::<Vec<_>>();
+
+  (0..n)
+    .map(|i| (0..n).map(|j| ((conditional[i][j] + conditional[j][i]) / (2.0 * n as f64)).max(1e-12)).collect())
+    .collect()
+}
@@ -0,0 +1,55 @@
+use nn::Sequential;
+
+/// Settings of the gradient ascent in `maximize_activation`.
+#[derive(Clone, Copy, Debug)]
+pub struct MaximizeConfig {
+  /// Radius of the L2 ball the input is kept in.
+  pub norm: f32,
+  /// Length of every step, which follows the normalised gradient.
+  pub step_size: f32,
+  pub iterations: usize,
+}
+
+impl Default for MaximizeConfig {
+  fn default() -> MaximizeConfig {
+    MaximizeConfig {
+      norm: 10.0,
+      step_size: 1.0,
+      iterations: 200,
+    }
+  }
+}
+
+impl Sequential {
+  /// The input of bounded norm that maximises `unit` of hidden layer `layer`, found by projected
+  /// gradient ascent from the zero input. Returns it with the activation it reaches.
+  pub fn maximize_activation(&self, layer: usize, unit: usize, conf: &MaximizeConfig) -> (Vec<f32>, f32) {
+    assert!(layer >= 2 && layer <= self.layers.len() + 1, "network of {} layers has no hidden layer {}", self.layers.len(), layer);
+
+    let mut input = vec![0.0; self.input_size()];
+    for _ in 0..conf.iterations {
+      let (_, gradient) = self.unit_input_gradient(input.clone(), layer, unit);
+      let length = norm(&gradient);
+      if length == 0.0 {
+        break;
+      }
+      for (x, g) in input.iter_mut().zip(&gradient) {
+        *x += conf.step_size * g / length;
+      }
+
+      let length = norm(&input);
+      if length > conf.norm {
+        for x in input.iter_mut() {
+          *x *= conf.norm / length;
+        }
+      }
+    }
+
+    let activation = self.eval_to_layer(input.clone(), layer)[unit];
+    (input, activation)
+  }
+}
+
+fn norm(values: &[f32]) -> f32 {
+  values.iter().map(|x| x * x).sum::<f32>().sqrt()
+}